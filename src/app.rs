@@ -1,15 +1,174 @@
-use crate::{config, ui::*};
-use crossterm::event::{Event, KeyCode, MouseEventKind};
-use rodio::{Decoder, Sink};
-use std::fs::File;
-use std::io::BufReader;
+use crate::{
+    browser::Browser,
+    config::{self, Config},
+    fade::Fader,
+    files::Files,
+    input::{Input, InputEvent},
+    keymap::{Action, Keymap},
+    library,
+    meta::Tags,
+    mode::{Repeat, Shuffle},
+    output::Output,
+    playlist::Playlists,
+    queue::Queue,
+    search::Search,
+    sort::Sort,
+    state::State,
+    ui::*,
+    visualizer::Visualizer,
+    volume::Volume,
+    watch::{self, Watch},
+};
+use color_eyre::Result;
+use crossterm::event::{Event, KeyCode, KeyEventKind, MouseButton, MouseEventKind};
+use ratatui::layout::Rect;
+use rodio::{Sink, Source};
+use serde_derive::Serialize;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, oneshot};
 
-/// `Song` structure contains the name and total time about this song.
+/// The next song is appended to the sink this many seconds before the current one ends, so
+/// there is no gap between them. Crossfaded songs are picked ahead by the crossfade length.
+const PRELOAD_SECS: f64 = 2.0;
+/// Going to the previous song restarts the current one once it has played this long.
+const RESTART_SECS: f64 = 3.0;
+/// Number of played songs remembered.
+const HISTORY_LEN: usize = 100;
+/// Messages stay in the status line this long.
+const STATUS_SECS: u64 = 3;
+
+/// Where the next song comes from, so it can be put back if it is not played after all.
+#[derive(Debug, Clone, Copy)]
+pub enum Origin {
+    Repeat,
+    Future,
+    Queue,
+    Shuffle,
+    Library,
+}
+
+/// Whether a song is playing, as shown to remote controls.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Playback {
+    Stopped,
+    Paused,
+    Playing,
+}
+
+/// The next song, picked a little before the current one ends.
 #[derive(Debug)]
+pub struct Preload {
+    pub song: Song,
+    origin: Origin,
+    /// The song is crossfaded into, instead of appended to the sink.
+    crossfade: bool,
+}
+
+/// `Song` structure contains the name, tags and total time about this song.
+#[derive(Debug, Clone, Serialize)]
 pub struct Song {
+    #[serde(rename = "path")]
     pub name: String,
+    /// Path relative to the music directory, keeps the folder layout.
+    pub rel_path: PathBuf,
     pub time: f64,
+    pub tags: Tags,
+    /// The song can not be decoded, it is skipped.
+    pub unplayable: bool,
+    /// When the song joined the library, in seconds since the epoch.
+    pub added: u64,
+}
+
+impl Song {
+    /// The title tag, or the file name without extension if the song has no title.
+    pub fn title(&self) -> String {
+        match &self.tags.title {
+            Some(title) => title.clone(),
+            None => self
+                .rel_path
+                .file_stem()
+                .unwrap_or(self.rel_path.as_os_str())
+                .to_string_lossy()
+                .to_string(),
+        }
+    }
+
+    /// The text shown for this song, `Artist - Title` when the artist is known.
+    pub fn display(&self) -> String {
+        match &self.tags.artist {
+            Some(artist) => format!("{} - {}", artist, self.title()),
+            None => self.title(),
+        }
+    }
+}
+
+/// `Scanning` tracks a library scan running in background.
+pub struct Scanning {
+    rx: mpsc::UnboundedReceiver<library::Event>,
+    /// Number of files to read.
+    pub total: usize,
+    /// Number of files already read.
+    pub done: usize,
+    /// Songs of a rescan, they replace the list at once when the scan finishes. The first scan
+    /// streams songs into the list directly instead.
+    songs: Option<Vec<Song>>,
+}
+
+/// `Restoring` tracks the queue of the last session, its songs are taken from the library once it
+/// is loaded and the others read in background.
+struct Restoring {
+    paths: Vec<PathBuf>,
+    /// The songs not in the library.
+    rx: Option<oneshot::Receiver<Vec<Song>>>,
+}
+
+/// What to do with the songs picked in the files tab, once they are read in background.
+enum Pick {
+    Play,
+    Enqueue,
+    PlayNext,
+    AddToPlaylist(String),
+}
+
+/// What the text typed in the prompt is for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptKind {
+    /// Go to a timestamp of the current song.
+    Seek,
+    /// Search the library, the list is filtered while typing.
+    Search,
+    NewPlaylist,
+    RenamePlaylist,
+    /// Add the selected songs to a playlist, it is created if needed.
+    AddToPlaylist,
+    /// Confirm the deletion of the selected playlist.
+    DeletePlaylist,
+    ImportPlaylist,
+    ExportPlaylist,
+}
+
+impl PromptKind {
+    pub fn title(self) -> &'static str {
+        match self {
+            PromptKind::Seek => "Go to: ",
+            PromptKind::Search => "/",
+            PromptKind::NewPlaylist => "New playlist: ",
+            PromptKind::RenamePlaylist => "Rename playlist: ",
+            PromptKind::AddToPlaylist => "Add to playlist: ",
+            PromptKind::DeletePlaylist => "Delete the playlist? (y/n) ",
+            PromptKind::ImportPlaylist => "Import playlist file: ",
+            PromptKind::ExportPlaylist => "Export playlist to: ",
+        }
+    }
+}
+
+/// `Prompt` is the input line shown at the bottom.
+pub struct Prompt {
+    pub kind: PromptKind,
+    pub input: Input,
 }
 
 /// `App` contains all neccessary elements when running.
@@ -18,70 +177,190 @@ pub struct App<'a> {
     pub progress: f64,
     pub tot_time: f64,
     pub cur_time: f64,
+    /// Index of the playing song in the library.
     pub cur_idx: Option<usize>,
+    /// The playing song.
+    pub current: Option<Song>,
+    /// The song to play after the current one, already appended to the sink unless it is
+    /// crossfaded into.
+    pub preloaded: Option<Preload>,
+    /// The songs played before the current one, the last one is the most recent.
+    pub history: Vec<Song>,
+    /// The songs gone back from with previous, the last one is played next.
+    pub future: Vec<Song>,
+    pub queue: Queue,
+    pub playlists: Playlists,
+    pub browser: Browser,
+    pub files: Files,
+    pub shuffle: Option<Shuffle>,
+    pub repeat: Repeat,
     pub start: bool,
     pub quit: bool,
-    pub barchart_data: Vec<(&'a str, u64)>,
+    pub visualizer: Visualizer,
     pub tasks: StatefulList<Song>,
     pub sink: Sink,
-    pub volume: f32,
+    output: Output,
+    /// Gain of the songs in the sink.
+    fader: Fader,
+    /// Sinks of the songs fading out, dropped once they are silent.
+    fading: Vec<(Sink, Fader)>,
+    /// The song is fading out, the sink is paused once it is silent.
+    pausing: bool,
+    pub volume: Volume,
     pub last_tick: Instant,
     pub select_tick: Instant,
+    pub config: Config,
+    pub keymap: Keymap,
+    pub scanning: Option<Scanning>,
+    /// Follows the changes of the music directory.
+    watch: Option<Watch>,
+    /// The changed paths whose songs are being read in background.
+    changing: Option<(Vec<PathBuf>, oneshot::Receiver<Vec<Song>>)>,
+    /// The saved queue, until its songs are found.
+    restoring: Option<Restoring>,
+    /// The songs picked in the files tab, being read in background.
+    picking: Option<(Pick, oneshot::Receiver<Result<Vec<Song>>>)>,
+    pub prompt: Option<Prompt>,
+    /// The last search, `n` and `N` jump between its matches.
+    pub search: Option<Search>,
+    /// The order of the library.
+    pub sort: Sort,
+    /// Message shown in the status line, and when it was shown.
+    pub status: Option<(String, Instant)>,
+    /// Where the line of the progress bar is drawn, used to seek by mouse.
+    pub gauge_line: Rect,
 }
 
 impl<'a> App<'a> {
-    /// Create the `App`
-    pub async fn new(sink: Sink) -> App<'a> {
-        App {
-            tabs: Tabstatus::new(vec!["Tab1", "Tab2"]),
+    /// Create the `App` with the volume of the last session, the library is empty until `scan`
+    /// is called.
+    pub fn new(
+        output: Output,
+        config: Config,
+        state: &State,
+        playlists: Playlists,
+    ) -> Result<App<'a>> {
+        let sink = output.sink()?;
+        let volume = state.volume();
+        sink.set_volume(volume.gain());
+        let keymap = Keymap::new(&config.keys)?;
+        Ok(App {
+            tabs: Tabstatus::new(vec![
+                "Library",
+                "Visualizer",
+                "Queue",
+                "Playlists",
+                "Browser",
+                "Files",
+            ]),
             progress: 0.0,
             quit: false,
             start: false,
-            barchart_data: vec![
-                ("B1", 9),
-                ("B2", 12),
-                ("B3", 5),
-                ("B4", 8),
-                ("B5", 2),
-                ("B6", 4),
-                ("B7", 5),
-                ("B8", 9),
-                ("B9", 14),
-                ("B10", 15),
-                ("B11", 1),
-                ("B12", 0),
-                ("B13", 4),
-                ("B14", 6),
-                ("B15", 4),
-                ("B16", 6),
-                ("B17", 4),
-                ("B18", 7),
-                ("B19", 13),
-                ("B20", 8),
-                ("B21", 11),
-                ("B22", 9),
-                ("B23", 3),
-                ("B24", 5),
-            ],
-            tasks: StatefulList::with_items(config::playlist().await.unwrap()),
+            visualizer: Visualizer::new(config.visualizer.clone()),
+            tasks: StatefulList::with_items(Vec::new()),
             tot_time: 0.0,
             cur_time: 0.0,
             sink,
+            output,
+            fader: Fader::new(1.0),
+            fading: Vec::new(),
+            pausing: false,
             cur_idx: None,
-            volume: 1.0,
+            current: None,
+            preloaded: None,
+            history: Vec::new(),
+            future: Vec::new(),
+            queue: Queue::new(),
+            playlists,
+            browser: Browser::new(),
+            files: Files::new(PathBuf::from(&config.path)),
+            shuffle: config
+                .playback
+                .shuffle
+                .then(|| Shuffle::new(config.playback.shuffle_seed)),
+            repeat: config.playback.repeat,
+            sort: config.sort,
+            volume,
             last_tick: Instant::now(),
             select_tick: Instant::now(),
+            config,
+            keymap,
+            scanning: None,
+            watch: None,
+            changing: None,
+            restoring: None,
+            picking: None,
+            prompt: None,
+            search: None,
+            status: None,
+            gauge_line: Rect::default(),
+        })
+    }
+
+    /// The list of songs shown in the current tab.
+    fn focused(&mut self) -> &mut StatefulList<Song> {
+        match self.tabs.index {
+            QUEUE_TAB => &mut self.queue.songs,
+            _ => &mut self.tasks,
         }
     }
 
     /// Select the previous song.
     pub fn select_up(&mut self) {
-        self.tasks.previous();
+        match self.tabs.index {
+            PLAYLIST_TAB => self.playlists.previous(),
+            BROWSER_TAB => self.browser.previous(),
+            FILES_TAB => self.files.entries.previous(),
+            _ => self.focused().previous(),
+        }
     }
 
     /// Select the next song.
     pub fn select_down(&mut self) {
-        self.tasks.next();
+        match self.tabs.index {
+            PLAYLIST_TAB => self.playlists.next(),
+            BROWSER_TAB => self.browser.next(),
+            FILES_TAB => self.files.entries.next(),
+            _ => self.focused().next(),
+        }
+    }
+
+    /// Move the focus to the left pane, or switch to the previous tab.
+    fn left(&mut self) {
+        let moved = match self.tabs.index {
+            PLAYLIST_TAB => self.playlists.focus(false),
+            BROWSER_TAB => self.browser.focus(false),
+            FILES_TAB => self
+                .files
+                .parent(&self.tasks.items, &self.config.scan)
+                .unwrap_or_else(|e| {
+                    self.notify(e.to_string());
+                    true
+                }),
+            _ => false,
+        };
+        if !moved {
+            self.tab_left();
+        }
+    }
+
+    /// Move the focus to the right pane, or switch to the next tab.
+    fn right(&mut self) {
+        let moved = match self.tabs.index {
+            PLAYLIST_TAB => self.playlists.focus(true),
+            BROWSER_TAB => self.browser.focus(true),
+            FILES_TAB => self
+                .files
+                .enter(&self.tasks.items, &self.config.scan)
+                .unwrap_or_else(|e| {
+                    self.notify(e.to_string());
+                    true
+                }),
+            _ => false,
+        };
+        if !moved {
+            self.tab_next();
+        }
     }
 
     /// Switch the next tab.
@@ -94,29 +373,72 @@ impl<'a> App<'a> {
         self.tabs.previous()
     }
 
-    /// Toggle whether pauses playback of this sink.
+    /// Toggle whether pauses playback of this sink, fading out and in if `fade` is set.
     pub fn toggle(&mut self) {
-        if self.sink.is_paused() {
-            self.sink.play()
+        let fade = self.config.playback.fade;
+        if self.sink.is_paused() || self.pausing {
+            self.pausing = false;
+            self.sink.play();
+            self.fader.fade_to(1.0, fade);
+        } else if fade > 0.0 && !self.sink.empty() {
+            self.pausing = true;
+            self.fader.fade_to(0.0, fade);
         } else {
             self.sink.pause()
         }
     }
 
+    /// Whether the playback is paused, or fading out to be paused.
+    pub fn is_paused(&self) -> bool {
+        self.sink.is_paused() || self.pausing
+    }
+
+    pub fn playback(&self) -> Playback {
+        match (&self.current, self.is_paused()) {
+            (None, _) => Playback::Stopped,
+            (Some(_), true) => Playback::Paused,
+            (Some(_), false) => Playback::Playing,
+        }
+    }
+
+    /// Move the songs of the sink to a new one and fade them out in `secs` seconds, the new sink
+    /// is empty and playing.
+    fn retire_sink(&mut self, secs: f64) {
+        self.pausing = false;
+        let sink = match self.output.sink() {
+            Ok(sink) => sink,
+            Err(e) => {
+                self.notify(e.to_string());
+                self.sink.stop();
+                self.fader.fade_to(1.0, 0.0);
+                return;
+            }
+        };
+        sink.set_volume(self.volume.gain());
+        let old = std::mem::replace(&mut self.sink, sink);
+        let fader = std::mem::replace(&mut self.fader, Fader::new(1.0));
+        self.visualizer.retire();
+        // A paused sink would never fade, it is silent already.
+        if secs > 0.0 && !old.is_paused() && !old.empty() {
+            fader.fade_to(0.0, secs);
+            self.fading.push((old, fader));
+        }
+    }
+
     /// The handle about the information needed.
     pub fn on_tick(&mut self) {
         self.set_progress();
-        let value = self.barchart_data.pop().unwrap();
-        self.barchart_data.insert(0, value);
+        self.visualizer
+            .update(!self.sink.empty() && !self.sink.is_paused());
     }
 
     /// Calculate the progress bar information.
     pub fn set_progress(&mut self) {
-        match self.cur_idx {
-            Some(i) => {
+        match &self.current {
+            Some(song) => {
                 let mut time = self.sink.get_pos().as_secs_f64();
                 self.cur_time = time;
-                self.tot_time = self.tasks.items[i].time;
+                self.tot_time = song.time;
                 match self.tot_time {
                     0.0 => self.progress = 0.0,
                     _ => {
@@ -137,7 +459,14 @@ impl<'a> App<'a> {
 
     /// Clear the queue of the sounds to play.
     pub fn clear_list(&mut self) {
-        self.sink.clear();
+        self.queue.clear();
+    }
+
+    /// Stop playing.
+    pub fn stop(&mut self) {
+        self.retire_sink(self.config.playback.fade);
+        self.unload();
+        self.set_current(None);
     }
 
     /// Get current selected song offset in `Song` Vec.
@@ -145,84 +474,1006 @@ impl<'a> App<'a> {
         self.tasks.state.selected().unwrap_or(0)
     }
 
-    /// Append a song to the queue of sounds to play.
-    fn append_list(&mut self, offset: usize) {
-        // let offset = self.get_offset();
-        let file = BufReader::new(File::open(&self.tasks.items[offset].name).unwrap());
-        let source = Decoder::new(file).unwrap();
-        self.sink.append(source);
+    /// Get the index of the song in library.
+    fn position(&self, name: &str) -> Option<usize> {
+        self.tasks.items.iter().position(|song| song.name == name)
     }
 
-    /// Add remaining songs to the queue of sounds to play.
-    fn load_list(&mut self, offset: usize) {
-        // Init the `self.cur_idx`.
-        self.cur_idx = Some(offset);
+    /// Append a song to the queue of sounds to play. A song which can not be decoded is marked as
+    /// unplayable.
+    fn append_list(&mut self, song: &mut Song) -> bool {
+        match library::decode(Path::new(&song.name)) {
+            Ok(source) => {
+                let source = source.amplify(self.config.replaygain.factor(&song.tags));
+                self.sink
+                    .append(self.visualizer.tap(self.fader.wrap(source)));
+                true
+            }
+            Err(e) => {
+                self.notify(e.to_string());
+                self.mark_unplayable(song);
+                false
+            }
+        }
+    }
+
+    /// Mark the song as unplayable everywhere it is listed, so it is skipped from now on.
+    fn mark_unplayable(&mut self, song: &mut Song) {
+        song.unplayable = true;
+        let lists = [
+            self.tasks.items.iter_mut(),
+            self.queue.songs.items.iter_mut(),
+            self.future.iter_mut(),
+        ];
+        for other in lists.into_iter().flatten() {
+            if other.name == song.name {
+                other.unplayable = true;
+            }
+        }
+    }
 
-        let n = self.tasks.items.len();
-        for i in offset..n {
-            self.append_list(i);
+    /// Mark the song as the playing one, and select it in library. The song played before is
+    /// remembered in history.
+    fn set_current(&mut self, song: Option<Song>) {
+        if let Some(old) = self.current.take() {
+            if !old.unplayable && song.as_ref().is_none_or(|song| song.name != old.name) {
+                self.history.push(old);
+                if self.history.len() > HISTORY_LEN {
+                    self.history.remove(0);
+                }
+            }
         }
+        self.cur_idx = song.as_ref().and_then(|song| self.position(&song.name));
+        if self.cur_idx.is_some() {
+            self.tasks.state.select(self.cur_idx);
+        }
+        self.start = song.is_some();
+        self.current = song;
+    }
+
+    /// Stop the current song and play this one. If it can not be played, it is the current song
+    /// with an empty sink, so the one after it is played next.
+    fn play_song(&mut self, mut song: Song) {
+        self.retire_sink(self.config.playback.fade);
+        self.unload();
+        self.append_list(&mut song);
         self.sink.play();
+        self.set_current(Some(song));
+    }
+
+    /// The song to play after the current one. The songs gone back from come first, then the
+    /// queue, then the songs of library in shuffled order or after the current one. Repeating the
+    /// current song is ignored when `skip` is set. Unplayable songs are skipped.
+    fn next_song(&mut self, skip: bool) -> Option<(Song, Origin)> {
+        if !skip && self.repeat == Repeat::One {
+            if let Some(song) = self.current.as_ref().filter(|song| !song.unplayable) {
+                return Some((song.clone(), Origin::Repeat));
+            }
+        }
+        if let Some(song) = pop_playable(|| self.future.pop()) {
+            return Some((song, Origin::Future));
+        }
+        if let Some(song) = pop_playable(|| self.queue.pop()) {
+            return Some((song, Origin::Queue));
+        }
+
+        let repeat = self.repeat == Repeat::All;
+        if let Some(shuffle) = &mut self.shuffle {
+            // The songs are dealt again and again on repeat, give up once all of them have been
+            // tried.
+            let mut tries = 2 * self.tasks.items.len() + 1;
+            let song = pop_playable(|| {
+                tries = tries.checked_sub(1)?;
+                shuffle.next(&self.tasks.items, self.current.as_ref(), repeat)
+            })?;
+            return Some((song, Origin::Shuffle));
+        }
+        let start = self.cur_idx? + 1;
+        let len = self.tasks.items.len();
+        let wrap = if repeat { start.min(len) } else { 0 };
+        let song = (start..len)
+            .chain(0..wrap)
+            .map(|i| &self.tasks.items[i])
+            .find(|song| !song.unplayable)?;
+        Some((song.clone(), Origin::Library))
+    }
+
+    /// Put the preloaded song back where it came from, since the playback has been interrupted.
+    fn unload(&mut self) {
+        let Some(Preload { song, origin, .. }) = self.preloaded.take() else {
+            return;
+        };
+        match origin {
+            Origin::Future => self.future.push(song),
+            Origin::Queue => self.queue.play_next(song),
+            Origin::Shuffle => {
+                if let Some(shuffle) = &mut self.shuffle {
+                    shuffle.put_back(song.name);
+                }
+            }
+            Origin::Repeat | Origin::Library => {}
+        }
+    }
+
+    /// Skip to the next song.
+    pub fn next(&mut self) {
+        if self.current.is_none() {
+            return;
+        }
+        self.unload();
+        match self.next_song(true) {
+            Some((song, _)) => self.play_song(song),
+            None => self.stop(),
+        }
+    }
+
+    /// Go back to the previous song in history, or restart the current one if it has played for
+    /// a few seconds.
+    pub fn previous(&mut self) {
+        if self.current.is_none() && self.history.is_empty() {
+            return;
+        }
+        if self.sink.get_pos().as_secs_f64() > RESTART_SECS && self.current.is_some() {
+            self.replay();
+            return;
+        }
+        match self.history.pop() {
+            Some(song) => {
+                // Keep the current song out of history, next comes back to it.
+                if let Some(current) = self.current.take() {
+                    self.future.push(current);
+                }
+                self.play_song(song);
+            }
+            None => self.replay(),
+        }
+    }
+
+    /// Turn the shuffle on or off, the songs are dealt again every time it is turned on.
+    pub fn toggle_shuffle(&mut self) {
+        self.shuffle = match self.shuffle {
+            Some(_) => None,
+            None => Some(Shuffle::new(self.config.playback.shuffle_seed)),
+        };
     }
 
-    /// Now, when you press the enter to play this song, it will add remaining songs to the queue
-    /// of sounds to play.
+    /// Sort the library by the next key.
+    pub fn cycle_sort(&mut self) {
+        self.sort.key = self.sort.key.cycle();
+        self.sort_library();
+        self.notify(self.sort.label());
+    }
+
+    /// Reverse the order of the library.
+    pub fn reverse_sort(&mut self) {
+        self.sort.descending = !self.sort.descending;
+        self.sort_library();
+        self.notify(self.sort.label());
+    }
+
+    /// Switch to the next repeat mode.
+    pub fn cycle_repeat(&mut self) {
+        self.repeat = self.repeat.cycle();
+    }
+
+    /// Whether the current song is crossfaded into this one. Songs of the same album are kept
+    /// gapless unless `crossfade_same_album` is set.
+    fn crossfades(&self, song: &Song) -> bool {
+        let playback = &self.config.playback;
+        if playback.crossfade <= 0.0 || self.tot_time == 0.0 {
+            return false;
+        }
+        playback.crossfade_same_album
+            || !self.current.as_ref().is_some_and(|current| {
+                current.tags.album.is_some() && current.tags.album == song.tags.album
+            })
+    }
+
+    /// Pick the next song when the current one is about to end, it is appended to the sink for a
+    /// gapless transition unless it is crossfaded into.
+    fn preload(&mut self) {
+        if self.preloaded.is_some() || self.current.is_none() || self.sink.len() != 1 {
+            return;
+        }
+        // Songs without a known duration are preloaded at once.
+        let lead = PRELOAD_SECS.max(self.config.playback.crossfade);
+        if self.tot_time > 0.0 && self.tot_time - self.cur_time > lead {
+            return;
+        }
+        if let Some((mut song, origin)) = self.next_song(false) {
+            let crossfade = self.crossfades(&song);
+            // The song after it is picked on the next tick.
+            if !crossfade && !self.append_list(&mut song) {
+                return;
+            }
+            self.preloaded = Some(Preload {
+                song,
+                origin,
+                crossfade,
+            });
+        }
+    }
+
+    /// Start the preloaded song in a new sink once the current one is within the crossfade of its
+    /// end, fading one out while the other fades in.
+    fn crossfade(&mut self) {
+        let remaining = self.tot_time - self.cur_time;
+        if !self
+            .preloaded
+            .as_ref()
+            .is_some_and(|preload| preload.crossfade)
+            || remaining > self.config.playback.crossfade
+            || self.sink.is_paused()
+            || self.pausing
+        {
+            return;
+        }
+        let Some(Preload { mut song, .. }) = self.preloaded.take() else {
+            return;
+        };
+        let secs = remaining.max(0.0);
+        self.retire_sink(secs);
+        self.fader.fade_to(0.0, 0.0);
+        self.fader.fade_to(1.0, secs);
+        self.append_list(&mut song);
+        self.set_current(Some(song));
+    }
+
+    /// Play the selected song. In library, the following songs are played after it once the queue
+    /// is empty. In queue, the song is taken out of the queue.
     pub fn play(&mut self) {
-        self.sink.stop();
-        let offset = self.get_offset();
-        self.load_list(offset);
-        self.tasks.state.select(Some(offset));
+        match self.tabs.index {
+            PLAYLIST_TAB => return self.play_playlist(),
+            BROWSER_TAB => return self.play_picked(self.browser.songs_to_play()),
+            FILES_TAB => return self.pick_files(Pick::Play),
+            _ => {}
+        }
+        let song = match self.tabs.index {
+            QUEUE_TAB => self.queue.remove(),
+            _ => {
+                let offset = self.get_offset();
+                self.tasks.items.get(offset).cloned()
+            }
+        };
+        if let Some(song) = song {
+            // A new song is picked, there is nothing to come back to.
+            self.future.clear();
+            self.play_song(song);
+        }
+    }
+
+    /// Play the selected playlist from the selected song, the rest of the playlist is queued.
+    fn play_playlist(&mut self) {
+        let playlists = &self.playlists;
+        let start = match playlists.focus_songs {
+            true => playlists.songs.state.selected().unwrap_or(0),
+            false => 0,
+        };
+        let songs = playlists
+            .songs
+            .items
+            .get(start..)
+            .unwrap_or_default()
+            .to_vec();
+        self.play_picked(songs);
+    }
+
+    /// Play the songs picked in a tab, the first one at once and the others from the queue.
+    pub fn play_picked(&mut self, songs: Vec<Song>) {
+        if !songs.is_empty() {
+            // New songs are picked, there is nothing to come back to.
+            self.future.clear();
+            self.play_songs(songs);
+        }
+    }
+
+    /// Play the songs given on the command line, the first one at once and the others from the
+    /// queue.
+    pub fn play_songs(&mut self, songs: Vec<Song>) {
+        let mut songs = songs.into_iter();
+        let Some(first) = songs.next() else {
+            return;
+        };
+        songs.for_each(|song| self.queue.enqueue(song));
+        self.play_song(first);
+    }
+
+    /// Read the songs picked in the files tab in background, `pick` is done once they are read.
+    fn pick_files(&mut self, pick: Pick) {
+        let play = matches!(pick, Pick::Play);
+        if let Some(rx) = self.files.read_selected(&self.config.scan, play) {
+            self.picking = Some((pick, rx));
+        }
+    }
+
+    /// Do what the songs of the files tab were picked for, once read.
+    fn poll_picking(&mut self) {
+        let Some((_, rx)) = &mut self.picking else {
+            return;
+        };
+        let songs = match rx.try_recv() {
+            Ok(songs) => songs,
+            Err(oneshot::error::TryRecvError::Empty) => return,
+            Err(oneshot::error::TryRecvError::Closed) => Ok(Vec::new()),
+        };
+        let Some((pick, _)) = self.picking.take() else {
+            return;
+        };
+        let songs = match songs {
+            Ok(songs) => songs,
+            Err(e) => return self.notify(e.to_string()),
+        };
+        match pick {
+            Pick::Play => self.play_picked(songs),
+            Pick::Enqueue => songs.into_iter().for_each(|song| self.queue.enqueue(song)),
+            Pick::PlayNext => {
+                for song in songs.into_iter().rev() {
+                    self.queue.play_next(song);
+                }
+            }
+            Pick::AddToPlaylist(name) => match self.playlists.add(&name, &songs) {
+                Ok(()) => self.notify(format!("Added {} songs to `{}`", songs.len(), name)),
+                Err(e) => self.notify(e.to_string()),
+            },
+        }
+    }
+
+    /// Add the selected songs to the end of the queue.
+    pub fn enqueue(&mut self) {
+        if self.tabs.index == FILES_TAB {
+            return self.pick_files(Pick::Enqueue);
+        }
+        for song in self.selected_songs() {
+            self.queue.enqueue(song);
+        }
+    }
+
+    /// Add the selected songs to the front of the queue.
+    pub fn play_next(&mut self) {
+        if self.tabs.index == FILES_TAB {
+            return self.pick_files(Pick::PlayNext);
+        }
+        for song in self.selected_songs().into_iter().rev() {
+            self.queue.play_next(song);
+        }
+    }
+
+    /// The song selected in library, or in the playlists tab the selected song or else the whole
+    /// selected playlist, or in the browser the selected artist, album or track. The songs of the
+    /// files tab are read in background by `pick_files`.
+    fn selected_songs(&self) -> Vec<Song> {
+        let (songs, selected) = match self.tabs.index {
+            QUEUE_TAB | FILES_TAB => return Vec::new(),
+            BROWSER_TAB => return self.browser.selected_songs(),
+            PLAYLIST_TAB if !self.playlists.focus_songs => {
+                return self.playlists.songs.items.clone();
+            }
+            PLAYLIST_TAB => (&self.playlists.songs, self.playlists.songs.state.selected()),
+            _ => (&self.tasks, self.tasks.state.selected()),
+        };
+        selected
+            .and_then(|i| songs.items.get(i))
+            .cloned()
+            .into_iter()
+            .collect()
     }
 
     /// Replay current song.
     pub fn replay(&mut self) {
-        match self.sink.try_seek(Duration::new(0, 0)) {
-            Ok(_) => {}
-            Err(e) => eprintln!("{}", e),
+        self.seek(Duration::ZERO);
+    }
+
+    /// Seek to the position of the current song.
+    pub fn seek(&mut self, pos: Duration) {
+        if self.current.is_none() {
+            return;
+        }
+        let pos = match self.tot_time {
+            0.0 => pos,
+            tot_time => pos.min(Duration::from_secs_f64(tot_time)),
+        };
+        match self.sink.try_seek(pos) {
+            Ok(_) => self.set_progress(),
+            Err(e) => self.notify(format!("Can not seek: {}", e)),
+        }
+    }
+
+    /// Seek forward, or backward if `secs` is negative.
+    pub fn seek_by(&mut self, secs: f64) {
+        let mut pos = (self.sink.get_pos().as_secs_f64() + secs).max(0.0);
+        if self.tot_time > 0.0 {
+            pos = pos.min(self.tot_time);
+        }
+        match Duration::try_from_secs_f64(pos) {
+            Ok(pos) => self.seek(pos),
+            Err(_) => self.notify(format!("Can not seek by {}s", secs)),
+        }
+    }
+
+    /// Seek to the clicked position of the progress bar.
+    fn click_gauge(&mut self, column: u16, row: u16) {
+        let line = self.gauge_line;
+        if row != line.y || column < line.x || column >= line.right() || self.tot_time == 0.0 {
+            return;
+        }
+        let ratio = f64::from(column - line.x) / f64::from(line.width);
+        self.seek(Duration::from_secs_f64(self.tot_time * ratio));
+    }
+
+    /// Show a message in the status line for a few seconds.
+    pub fn notify(&mut self, msg: impl Into<String>) {
+        self.status = Some((msg.into(), Instant::now()));
+    }
+
+    /// Open the prompt.
+    pub fn open_prompt(&mut self, kind: PromptKind) {
+        self.prompt = Some(Prompt {
+            kind,
+            input: Input::new(),
+        });
+    }
+
+    /// Open the prompt with a text to edit.
+    fn open_prompt_with(&mut self, kind: PromptKind, value: &str) {
+        self.open_prompt(kind);
+        if let Some(prompt) = &mut self.prompt {
+            prompt.input.set_value(value);
+        }
+    }
+
+    /// Open the prompt for a playlist action, the ones on the selected playlist only work in the
+    /// playlists tab.
+    fn playlist_prompt(&mut self, kind: PromptKind) {
+        let name = self
+            .playlists
+            .selected()
+            .map(|playlist| playlist.name.clone())
+            .filter(|_| self.tabs.index == PLAYLIST_TAB);
+        match (kind, name) {
+            (PromptKind::NewPlaylist | PromptKind::ImportPlaylist, _) => self.open_prompt(kind),
+            (PromptKind::AddToPlaylist, name)
+                if !self.selected_songs().is_empty()
+                    || (self.tabs.index == FILES_TAB && self.files.selected().is_some()) =>
+            {
+                self.open_prompt_with(kind, name.as_deref().unwrap_or_default())
+            }
+            (PromptKind::RenamePlaylist, Some(name)) => self.open_prompt_with(kind, &name),
+            (PromptKind::DeletePlaylist | PromptKind::ExportPlaylist, Some(_)) => {
+                self.open_prompt(kind)
+            }
+            _ => {}
+        }
+    }
+
+    /// Run the playlist action submitted in the prompt.
+    fn submit_playlist(&mut self, kind: PromptKind, text: &str) -> Result<()> {
+        let path = || PathBuf::from(config::expand_var(text.trim()));
+        match kind {
+            PromptKind::NewPlaylist => self.playlists.create(text)?,
+            PromptKind::RenamePlaylist => self.playlists.rename(text)?,
+            PromptKind::AddToPlaylist if self.tabs.index == FILES_TAB => {
+                self.pick_files(Pick::AddToPlaylist(text.trim().to_string()));
+            }
+            PromptKind::AddToPlaylist => {
+                let songs = self.selected_songs();
+                self.playlists.add(text, &songs)?;
+                self.notify(format!("Added {} songs to `{}`", songs.len(), text.trim()));
+            }
+            PromptKind::DeletePlaylist if text.trim().eq_ignore_ascii_case("y") => {
+                self.playlists.delete()?
+            }
+            PromptKind::ImportPlaylist => {
+                let name = self.playlists.import(&path())?;
+                self.notify(format!("Imported `{}`", name));
+            }
+            PromptKind::ExportPlaylist => {
+                self.playlists.export(&path())?;
+                self.notify(format!("Exported to `{}`", path().display()));
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Do what the text submitted in the prompt is for.
+    fn submit_prompt(&mut self, kind: PromptKind, text: &str) {
+        match kind {
+            PromptKind::Seek => match parse_timestamp(text) {
+                Some(pos) => self.seek(pos),
+                None => self.notify(format!("Invalid timestamp `{}`", text)),
+            },
+            PromptKind::Search => {
+                let selected = self.search.as_ref().and_then(|search| search.selected());
+                match selected {
+                    _ if text.trim().is_empty() => self.search = None,
+                    Some(idx) => self.tasks.state.select(Some(idx)),
+                    None => {
+                        self.search = None;
+                        self.notify(format!("No match for `{}`", text));
+                    }
+                }
+            }
+            kind => {
+                if let Err(e) = self.submit_playlist(kind, text) {
+                    self.notify(e.to_string());
+                }
+            }
+        }
+    }
+
+    /// Open the search prompt, the library is searched whatever the tab.
+    fn start_search(&mut self) {
+        self.tabs.index = LIBRARY_TAB;
+        self.search = Some(Search::new("", &self.tasks.items));
+        self.open_prompt(PromptKind::Search);
+    }
+
+    /// Select the next match of the last search, or the previous one if not `forward`.
+    fn search_next(&mut self, forward: bool) {
+        let Some(search) = &self.search else {
+            return;
+        };
+        if let Some(idx) = search.next(self.tasks.state.selected(), forward) {
+            self.tasks.state.select(Some(idx));
+        }
+    }
+
+    /// Whether the query of a search is being typed.
+    pub fn is_searching(&self) -> bool {
+        self.prompt
+            .as_ref()
+            .is_some_and(|prompt| prompt.kind == PromptKind::Search)
+    }
+
+    /// Scan the music directory in background, the songs are added to the list as soon as they are
+    /// read.
+    pub fn scan(&mut self) {
+        self.scanning = Some(Scanning {
+            rx: library::spawn_scan(self.config.clone()),
+            total: 0,
+            done: 0,
+            songs: None,
+        });
+        self.watch();
+    }
+
+    /// Watch the music directory for changes, unless `watch` is off in `[scan]`.
+    fn watch(&mut self) {
+        self.watch = None;
+        self.changing = None;
+        if !self.config.scan.watch {
+            return;
+        }
+        match Watch::new(Path::new(&self.config.path)) {
+            Ok(watch) => self.watch = Some(watch),
+            Err(e) => self.notify(e.to_string()),
+        }
+    }
+
+    /// Read the changes of the music directory in background once the scan is done, the songs
+    /// are applied to the library when read. Missed changes start a rescan.
+    fn poll_watch(&mut self) {
+        self.poll_changing();
+        if self.scanning.is_some() || self.changing.is_some() {
+            return;
+        }
+        let Some(watch) = &mut self.watch else {
+            return;
+        };
+        match watch.poll() {
+            Ok(Some(changed)) => {
+                let rx = library::spawn_changed(self.config.clone(), changed.clone());
+                self.changing = Some((changed, rx));
+            }
+            Ok(None) => {}
+            Err(e) => {
+                self.notify(format!("{}, rescanning", e));
+                self.rescan();
+            }
+        }
+    }
+
+    /// Apply the songs read for the changed paths to the library.
+    fn poll_changing(&mut self) {
+        let Some((_, rx)) = &mut self.changing else {
+            return;
+        };
+        let songs = match rx.try_recv() {
+            Ok(songs) => songs,
+            Err(oneshot::error::TryRecvError::Empty) => return,
+            Err(oneshot::error::TryRecvError::Closed) => {
+                self.changing = None;
+                return;
+            }
+        };
+        let Some((changed, _)) = self.changing.take() else {
+            return;
+        };
+        let mut counts = (0, 0);
+        self.update_library(|library| counts = watch::apply(library, &changed, songs));
+        if counts != (0, 0) {
+            let (added, removed) = counts;
+            self.notify(format!(
+                "Library updated, {} added, {} removed",
+                added, removed
+            ));
+        }
+    }
+
+    /// Make the directory shown in the files tab the music directory, it is saved to the config
+    /// file and scanned. A scan of the old directory still running is dropped.
+    pub fn set_root(&mut self) {
+        if self.tabs.index != FILES_TAB {
+            return;
+        }
+        let dir = self.files.dir.clone();
+        match self.config.set_path(&dir) {
+            Ok(()) => {
+                self.notify(format!("Library root set to {}", dir.display()));
+                self.scanning = None;
+                self.rescan();
+                self.watch();
+            }
+            Err(e) => self.notify(e.to_string()),
         }
     }
 
+    /// Rescan the music directory in background, the list is replaced when it is done.
+    pub fn rescan(&mut self) {
+        if self.scanning.is_none() {
+            self.scanning = Some(Scanning {
+                rx: library::spawn_scan(self.config.clone()),
+                total: 0,
+                done: 0,
+                songs: Some(Vec::new()),
+            });
+        }
+    }
+
+    /// Replace the songs, keeps the selected and playing song if they are still there.
+    fn set_library(&mut self, songs: Vec<Song>) {
+        self.update_library(|items| *items = songs);
+    }
+
+    /// Sort the library again, after the sort order changed or the first scan is done.
+    fn sort_library(&mut self) {
+        self.update_library(|_| {});
+    }
+
+    /// Change the songs with `f` then sort them, the selected and playing songs are found again
+    /// by name.
+    fn update_library(&mut self, f: impl FnOnce(&mut Vec<Song>)) {
+        let selected = self
+            .tasks
+            .state
+            .selected()
+            .and_then(|idx| self.tasks.items.get(idx))
+            .map(|song| song.name.clone());
+
+        f(&mut self.tasks.items);
+        self.browser.invalidate();
+        self.playlists.invalidate();
+        let sort = self.sort;
+        self.tasks.items.sort_by(|a, b| sort.compare(a, b));
+        let selected = selected.and_then(|name| self.position(&name));
+        // The matches are indices into the library.
+        if let Some(search) = &self.search {
+            self.search = Some(Search::new(&search.query, &self.tasks.items));
+        }
+        self.cur_idx = self
+            .current
+            .as_ref()
+            .and_then(|song| self.position(&song.name));
+        self.tasks.state.select(selected);
+    }
+
+    /// Receive the songs read by the background scan.
+    fn poll_scan(&mut self) {
+        let Some(scanning) = &mut self.scanning else {
+            return;
+        };
+        while let Ok(event) = scanning.rx.try_recv() {
+            match event {
+                library::Event::Total(total) => scanning.total = total,
+                library::Event::Song(song) => {
+                    scanning.done += 1;
+                    match &mut scanning.songs {
+                        Some(songs) => songs.push(*song),
+                        None => self.tasks.items.push(*song),
+                    }
+                }
+                library::Event::Done(result) => {
+                    // Keep the current songs if the rescan failed.
+                    let songs = scanning.songs.take();
+                    self.scanning = None;
+                    match (result, songs) {
+                        (Ok(()), Some(songs)) => self.set_library(songs),
+                        (Ok(()), None) => self.sort_library(),
+                        (Err(e), _) => self.notify(e.to_string()),
+                    }
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Put the saved queue back once the scan is done. Songs of the library are taken from it,
+    /// the others are read in background.
+    fn poll_restore(&mut self) {
+        if self.scanning.is_some() {
+            return;
+        }
+        let Some(restoring) = &mut self.restoring else {
+            return;
+        };
+        let by_name: HashMap<&str, &Song> = self
+            .tasks
+            .items
+            .iter()
+            .map(|song| (song.name.as_str(), song))
+            .collect();
+        let Some(rx) = &mut restoring.rx else {
+            let missing = restoring
+                .paths
+                .iter()
+                .filter(|path| !by_name.contains_key(path.to_string_lossy().as_ref()))
+                .cloned()
+                .collect();
+            restoring.rx = Some(library::spawn_read(missing));
+            return;
+        };
+        let read = match rx.try_recv() {
+            Ok(songs) => songs,
+            Err(oneshot::error::TryRecvError::Empty) => return,
+            Err(oneshot::error::TryRecvError::Closed) => Vec::new(),
+        };
+        let mut read: HashMap<String, Song> = read
+            .into_iter()
+            .map(|song| (song.name.clone(), song))
+            .collect();
+        let songs = restoring
+            .paths
+            .iter()
+            .filter_map(|path| {
+                let name = path.to_string_lossy();
+                match by_name.get(name.as_ref()) {
+                    Some(&song) => Some(song.clone()),
+                    None => read.remove(name.as_ref()),
+                }
+            })
+            .collect();
+        self.queue.prepend(songs);
+        self.restoring = None;
+    }
+
+    /// Set the volume of the playing and the fading songs.
+    fn apply_volume(&mut self) {
+        let gain = self.volume.gain();
+        self.sink.set_volume(gain);
+        for (sink, _) in &self.fading {
+            sink.set_volume(gain);
+        }
+    }
+
+    /// Set the volume level in percent.
+    pub fn set_volume(&mut self, level: u8) {
+        self.volume = Volume::new(level, false);
+        self.apply_volume();
+    }
+
+    /// Change the volume level by `delta` percent.
+    pub fn change_volume(&mut self, delta: i16) {
+        self.volume.change(delta);
+        self.apply_volume();
+    }
+
     /// Increase the volume of the sound.
     pub fn increase_volume(&mut self) {
-        self.volume += 0.2;
-        self.sink.set_volume(self.volume);
+        self.change_volume(i16::from(self.config.playback.volume_step));
     }
 
     /// Decrease the volume of the sound.
     pub fn decrease_volume(&mut self) {
-        self.volume -= 0.2;
-        self.sink.set_volume(self.volume);
+        self.change_volume(-i16::from(self.config.playback.volume_step));
     }
 
-    /// Handle the key and mouse events.
-    pub fn handle_events(&mut self, ev: Event) {
-        match ev {
-            Event::Key(key) => match key.code {
-                KeyCode::Enter => {
-                    self.play();
-                    self.start = true;
+    /// Mute or unmute the sound.
+    pub fn toggle_mute(&mut self) {
+        self.volume.toggle_mute();
+        self.apply_volume();
+    }
+
+    /// What is remembered for the next session.
+    fn state(&self) -> State {
+        let song = self.current.as_ref().filter(|song| !song.unplayable);
+        State {
+            volume: Some(self.volume.level()),
+            muted: self.volume.is_muted(),
+            song: song.map(|song| PathBuf::from(&song.name)),
+            position: song.map_or(0.0, |_| self.sink.get_pos().as_secs_f64()),
+            // The saved queue not restored yet is kept.
+            queue: self
+                .restoring
+                .iter()
+                .flat_map(|restoring| restoring.paths.iter().cloned())
+                .chain(
+                    self.queue
+                        .songs
+                        .items
+                        .iter()
+                        .map(|song| PathBuf::from(&song.name)),
+                )
+                .collect(),
+            shuffle: Some(self.shuffle.is_some()),
+            repeat: Some(self.repeat),
+            tab: Some(self.tabs.index),
+        }
+    }
+
+    /// Remember the state for the next session.
+    pub fn save_state(&self) -> Result<()> {
+        self.state().save()
+    }
+
+    /// Restore the last session unless `resume` is off. The queue and the song are left alone if
+    /// `play` is false, there are other songs to play.
+    pub fn resume(&mut self, state: State, play: bool) {
+        if self.config.playback.resume {
+            self.restore(state, play);
+        }
+    }
+
+    /// Restore the modes, the tab, the queue and the song of a session, the song is paused where
+    /// it was. The queue follows once the library is loaded. Files which are gone are skipped.
+    fn restore(&mut self, state: State, play: bool) {
+        if let Some(shuffle) = state.shuffle {
+            self.shuffle = shuffle.then(|| Shuffle::new(self.config.playback.shuffle_seed));
+        }
+        self.repeat = state.repeat.unwrap_or(self.repeat);
+        if let Some(tab) = state.tab.filter(|&tab| tab < self.tabs.titles.len()) {
+            self.tabs.index = tab;
+        }
+        if !play {
+            return;
+        }
+        if !state.queue.is_empty() {
+            self.restoring = Some(Restoring {
+                paths: state.queue,
+                rx: None,
+            });
+        }
+        if let Some(path) = state.song.filter(|path| path.exists()) {
+            let mut song = library::read_song(&path);
+            // Paused before the song is appended, so not a sample is heard before the seek.
+            self.sink.pause();
+            if self.append_list(&mut song) {
+                self.set_current(Some(song));
+                self.seek(Duration::from_secs_f64(state.position));
+            }
+        }
+    }
+
+    /// Do the action bound to the keys.
+    pub fn dispatch(&mut self, action: Action) {
+        match action {
+            Action::Quit => self.quit = true,
+            Action::Up => self.select_up(),
+            Action::Down => self.select_down(),
+            Action::Left => self.left(),
+            Action::Right => self.right(),
+            Action::TabPrev => self.tab_left(),
+            Action::TabNext => self.tab_next(),
+            Action::Top if self.tabs.index == PLAYLIST_TAB => self.playlists.first(),
+            Action::Bottom if self.tabs.index == PLAYLIST_TAB => self.playlists.last(),
+            Action::Top if self.tabs.index == BROWSER_TAB => self.browser.first(),
+            Action::Bottom if self.tabs.index == BROWSER_TAB => self.browser.last(),
+            Action::Top if self.tabs.index == FILES_TAB => self.files.entries.first(),
+            Action::Bottom if self.tabs.index == FILES_TAB => self.files.entries.last(),
+            Action::Top => self.focused().first(),
+            Action::Bottom => self.focused().last(),
+            Action::Play => self.play(),
+            Action::Toggle => self.toggle(),
+            Action::Stop => self.stop(),
+            Action::Next => self.next(),
+            Action::Previous => self.previous(),
+            Action::Replay => self.replay(),
+            Action::SeekForward => self.seek_by(self.config.playback.seek_step),
+            Action::SeekBackward => self.seek_by(-self.config.playback.seek_step),
+            Action::SeekForwardLong => self.seek_by(self.config.playback.seek_step_long),
+            Action::SeekBackwardLong => self.seek_by(-self.config.playback.seek_step_long),
+            Action::GoTo => self.open_prompt(PromptKind::Seek),
+            Action::Enqueue => self.enqueue(),
+            Action::PlayNext => self.play_next(),
+            Action::Remove => match self.tabs.index {
+                QUEUE_TAB => {
+                    self.queue.remove();
                 }
-                KeyCode::Char(c) => {
-                    match c {
-                        'q' => self.quit = true,
-                        'h' => self.tab_left(),
-                        'l' => self.tab_next(),
-                        ' ' => self.toggle(),
-                        'j' => self.select_down(),
-                        'k' => self.select_up(),
-                        'r' => self.replay(),
-                        'e' => self.clear_list(),
-                        '+' => self.increase_volume(),
-                        '-' => self.decrease_volume(),
-                        _ => {}
+                PLAYLIST_TAB if self.playlists.focus_songs => {
+                    if let Err(e) = self.playlists.remove_song() {
+                        self.notify(e.to_string());
                     }
-                    self.select_tick = Instant::now();
                 }
+                PLAYLIST_TAB => self.playlist_prompt(PromptKind::DeletePlaylist),
                 _ => {}
             },
+            Action::MoveUp => {
+                if self.tabs.index == QUEUE_TAB {
+                    self.queue.move_up();
+                }
+            }
+            Action::MoveDown => {
+                if self.tabs.index == QUEUE_TAB {
+                    self.queue.move_down();
+                }
+            }
+            Action::Clear => self.clear_list(),
+            Action::Shuffle => self.toggle_shuffle(),
+            Action::Repeat => self.cycle_repeat(),
+            Action::Sort => self.cycle_sort(),
+            Action::SortReverse => self.reverse_sort(),
+            Action::Rescan => self.rescan(),
+            Action::VolumeUp => self.increase_volume(),
+            Action::VolumeDown => self.decrease_volume(),
+            Action::Mute => self.toggle_mute(),
+            Action::Search => self.start_search(),
+            Action::SearchNext => self.search_next(true),
+            Action::SearchPrev => self.search_next(false),
+            Action::ClearSearch => self.search = None,
+            Action::NewPlaylist => self.playlist_prompt(PromptKind::NewPlaylist),
+            Action::RenamePlaylist => self.playlist_prompt(PromptKind::RenamePlaylist),
+            Action::AddToPlaylist => self.playlist_prompt(PromptKind::AddToPlaylist),
+            Action::ImportPlaylist => self.playlist_prompt(PromptKind::ImportPlaylist),
+            Action::ExportPlaylist => self.playlist_prompt(PromptKind::ExportPlaylist),
+            Action::SetRoot => self.set_root(),
+        }
+        self.select_tick = Instant::now();
+    }
+
+    /// Handle the key and mouse events.
+    pub fn handle_events(&mut self, ev: Event) {
+        match ev {
+            Event::Key(key) if key.kind == KeyEventKind::Press => {
+                // Keys go to the prompt while it is open.
+                if let Some(mut prompt) = self.prompt.take() {
+                    let search = self
+                        .search
+                        .as_mut()
+                        .filter(|_| prompt.kind == PromptKind::Search);
+                    match (key.code, search) {
+                        // The arrows move between the matches while the query is typed.
+                        (KeyCode::Up, Some(search)) => search.select_by(false),
+                        (KeyCode::Down, Some(search)) => search.select_by(true),
+                        _ => match prompt.input.handle_key(key) {
+                            InputEvent::Edit if prompt.kind == PromptKind::Search => {
+                                self.search =
+                                    Some(Search::new(prompt.input.value(), &self.tasks.items));
+                            }
+                            InputEvent::Edit => {}
+                            InputEvent::Submit => {
+                                self.submit_prompt(prompt.kind, &prompt.input.path);
+                                return;
+                            }
+                            InputEvent::Cancel => {
+                                if prompt.kind == PromptKind::Search {
+                                    self.search = None;
+                                }
+                                return;
+                            }
+                        },
+                    }
+                    self.prompt = Some(prompt);
+                    return;
+                }
+                if let Some(action) = self.keymap.feed(key) {
+                    self.dispatch(action);
+                }
+            }
             Event::Mouse(mouse) => match mouse.kind {
                 MouseEventKind::ScrollDown => self.select_down(),
                 MouseEventKind::ScrollUp => self.select_up(),
+                MouseEventKind::Down(MouseButton::Left) => {
+                    self.click_gauge(mouse.column, mouse.row)
+                }
                 _ => {}
             },
             _ => {}
@@ -231,21 +1482,66 @@ impl<'a> App<'a> {
 
     /// Update all components in `App`.
     pub fn update(&mut self, tick_rate: Duration) {
-        if self.last_tick.elapsed() >= tick_rate {
-            if self.start && self.sink.empty() {
-                self.tasks.state.select(None);
-                self.cur_idx = None;
-                self.start = false;
+        self.poll_scan();
+        self.poll_restore();
+        self.poll_watch();
+        self.poll_picking();
+        self.files.poll();
+        match self.tabs.index {
+            PLAYLIST_TAB => self.playlists.refresh(&self.tasks.items),
+            BROWSER_TAB => self.browser.refresh(&self.tasks.items),
+            FILES_TAB => {
+                if let Err(e) = self.files.refresh(&self.tasks.items, &self.config.scan) {
+                    self.notify(e.to_string());
+                }
             }
+            _ => {}
+        }
 
-            // Update the select song ui when the song play over.
-            if let Some(cur_idx) = self.cur_idx {
-                if self.tasks.items.len() - self.sink.len() > cur_idx {
-                    self.cur_idx = Some(self.tasks.items.len() - self.sink.len());
-                    self.tasks.state.select(self.cur_idx);
+        // The preloaded song starts once the current one is over.
+        if self
+            .preloaded
+            .as_ref()
+            .is_some_and(|preload| !preload.crossfade)
+            && self.sink.len() <= 1
+        {
+            let song = self.preloaded.take().map(|preload| preload.song);
+            self.set_current(song);
+        }
+        if self.start && self.sink.empty() {
+            // The song to crossfade into is played at once if the current one ended early.
+            let next = match self.preloaded.take() {
+                Some(Preload { song, origin, .. }) => Some((song, origin)),
+                None => self.next_song(false),
+            };
+            match next {
+                Some((song, _)) => self.play_song(song),
+                None => {
+                    self.tasks.state.select(None);
+                    self.set_current(None);
                 }
             }
+        }
+
+        if self.pausing && self.fader.is_silent() {
+            self.pausing = false;
+            self.sink.pause();
+        }
+        self.fading
+            .retain(|(sink, fader)| !sink.empty() && !fader.is_silent());
+
+        if self
+            .status
+            .as_ref()
+            .is_some_and(|(_, time)| time.elapsed() >= Duration::from_secs(STATUS_SECS))
+        {
+            self.status = None;
+        }
+
+        if self.last_tick.elapsed() >= tick_rate {
             self.on_tick();
+            self.preload();
+            self.crossfade();
             self.last_tick = Instant::now();
         }
     }
@@ -253,9 +1549,9 @@ impl<'a> App<'a> {
     /// Recover the select ui.
     pub fn recover_select(&mut self, tick_rate: Duration) {
         if self.select_tick.elapsed() >= tick_rate {
-            if self.cur_idx.is_some() && (!self.sink.empty() || self.cur_idx.is_some()) {
+            if let Some(cur_idx) = self.cur_idx {
                 if let Some(i) = self.tasks.state.selected() {
-                    if i != self.cur_idx.unwrap() {
+                    if i != cur_idx {
                         self.tasks.state.select(self.cur_idx)
                     }
                 }
@@ -264,3 +1560,147 @@ impl<'a> App<'a> {
         }
     }
 }
+
+/// Take songs until a playable one.
+fn pop_playable(pop: impl FnMut() -> Option<Song>) -> Option<Song> {
+    std::iter::from_fn(pop).find(|song| !song.unplayable)
+}
+
+/// Parse a timestamp like `83`, `1:23` or `1:02:03.5`.
+pub fn parse_timestamp(s: &str) -> Option<Duration> {
+    let parts: Vec<&str> = s.trim().split(':').collect();
+    if parts.len() > 3 {
+        return None;
+    }
+
+    let mut secs = 0.0;
+    for (i, part) in parts.iter().enumerate() {
+        let last = i + 1 == parts.len();
+        let valid = !part.is_empty()
+            && part
+                .chars()
+                .all(|c| c.is_ascii_digit() || (last && c == '.'));
+        if !valid {
+            return None;
+        }
+        let value: f64 = part.parse().ok()?;
+        // Minutes and seconds after the first part must be below 60.
+        if i > 0 && value >= 60.0 {
+            return None;
+        }
+        secs = secs * 60.0 + value;
+    }
+    // Too large for a `Duration`, e.g. a long run of digits.
+    Duration::try_from_secs_f64(secs).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("83"), Some(Duration::from_secs(83)));
+        assert_eq!(parse_timestamp("1:23"), Some(Duration::from_secs(83)));
+        assert_eq!(
+            parse_timestamp(" 1:02:03 "),
+            Some(Duration::from_secs(3723))
+        );
+        assert_eq!(parse_timestamp("0:01.5"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_timestamp("1:60"), None);
+        assert_eq!(parse_timestamp("1.5:00"), None);
+        assert_eq!(parse_timestamp("-1"), None);
+        assert_eq!(parse_timestamp(""), None);
+        assert_eq!(parse_timestamp("1:2:3:4"), None);
+        assert_eq!(parse_timestamp("99999999999999999999999"), None);
+    }
+
+    #[test]
+    fn test_move_empty() {
+        let dir = testing::temp_dir("empty");
+        let mut app = testing::fake_app(&dir);
+        app.tabs.index = QUEUE_TAB;
+        for _ in 0..2 {
+            app.select_down();
+        }
+        for _ in 0..2 {
+            app.select_up();
+        }
+        assert_eq!(app.queue.songs.state.selected(), None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_set_root() {
+        let dir = testing::temp_dir("set-root");
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        testing::write_wav(&dir.join("a.wav"), 1);
+        testing::write_wav(&dir.join("sub/b.wav"), 1);
+        let mut app = testing::fake_app(&dir);
+        app.config.file = Some(dir.join("config.toml"));
+        app.rescan();
+        app.tabs.index = FILES_TAB;
+        app.files.dir = dir.join("sub");
+        // Replaces the scan of the old root.
+        app.set_root();
+        let start = Instant::now();
+        while app.scanning.is_some() && start.elapsed() < Duration::from_secs(5) {
+            app.update(Duration::from_secs(1));
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        let names: Vec<&str> = app
+            .tasks
+            .items
+            .iter()
+            .map(|song| song.name.as_str())
+            .collect();
+        assert_eq!(names, [dir.join("sub/b.wav").to_str().unwrap()]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_resume() {
+        let dir = testing::temp_dir("resume");
+        let (a, b) = (dir.join("a.wav"), dir.join("b.wav"));
+        testing::write_wav(&a, 5);
+        testing::write_wav(&b, 5);
+        let state = State {
+            song: Some(a.clone()),
+            position: 2.5,
+            queue: vec![b.clone(), dir.join("gone.wav")],
+            shuffle: Some(true),
+            repeat: Some(Repeat::One),
+            tab: Some(QUEUE_TAB),
+            ..State::default()
+        };
+
+        let mut app = testing::fake_app(&dir);
+        app.restore(state, true);
+        // Kept until restored.
+        assert_eq!(app.state().queue, [b.clone(), dir.join("gone.wav")]);
+        let start = Instant::now();
+        while app.restoring.is_some() && start.elapsed() < Duration::from_secs(5) {
+            app.update(Duration::from_secs(1));
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert_eq!(app.playback(), Playback::Paused);
+        assert_eq!(app.cur_idx, Some(0));
+        assert!((app.sink.get_pos().as_secs_f64() - 2.5).abs() < 0.1);
+        assert!(app.shuffle.is_some() && app.repeat == Repeat::One);
+        assert_eq!(app.tabs.index, QUEUE_TAB);
+
+        let state = app.state();
+        assert_eq!(state.song, Some(a));
+        assert_eq!(state.queue, [b]);
+        assert_eq!(state.tab, Some(QUEUE_TAB));
+
+        // Songs to play replace the saved ones.
+        let mut app = testing::fake_app(&dir);
+        app.restore(state, false);
+        assert_eq!(app.playback(), Playback::Stopped);
+        assert!(app.queue.songs.items.is_empty());
+        assert_eq!(app.tabs.index, QUEUE_TAB);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
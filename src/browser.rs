@@ -0,0 +1,363 @@
+use std::collections::HashMap;
+
+use crate::{
+    app::Song,
+    sort::{natural_cmp, Sort, SortKey},
+    ui::StatefulList,
+};
+
+const UNKNOWN_ARTIST: &str = "Unknown artist";
+const UNKNOWN_ALBUM: &str = "Unknown album";
+
+/// An album with its songs in track order.
+pub struct Album {
+    pub name: String,
+    pub songs: Vec<Song>,
+}
+
+/// An artist with its albums in name order.
+pub struct Artist {
+    pub name: String,
+    pub albums: Vec<Album>,
+}
+
+/// A column of the browser.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    Artists,
+    Albums,
+    Tracks,
+}
+
+/// `Browser` groups the library by artist, then album, then track, in three columns.
+pub struct Browser {
+    pub artists: StatefulList<Artist>,
+    /// Albums of the selected artist.
+    pub albums: StatefulList<String>,
+    /// Songs of the selected album.
+    pub tracks: StatefulList<Song>,
+    /// The column with the focus.
+    pub column: Column,
+    /// Length of the library the columns were built for, `None` once it changed.
+    shown: Option<usize>,
+}
+
+impl Browser {
+    pub fn new() -> Self {
+        Self {
+            artists: StatefulList::with_items(Vec::new()),
+            albums: StatefulList::with_items(Vec::new()),
+            tracks: StatefulList::with_items(Vec::new()),
+            column: Column::Artists,
+            shown: None,
+        }
+    }
+
+    pub fn artist(&self) -> Option<&Artist> {
+        self.artists.items.get(self.artists.state.selected()?)
+    }
+
+    pub fn album(&self) -> Option<&Album> {
+        self.artist()?.albums.get(self.albums.state.selected()?)
+    }
+
+    fn track(&self) -> Option<&Song> {
+        self.tracks.items.get(self.tracks.state.selected()?)
+    }
+
+    /// Rebuild the columns on the next refresh, the library changed.
+    pub fn invalidate(&mut self) {
+        self.shown = None;
+    }
+
+    /// Group the library again if it changed, the selected artist, album and track are kept.
+    pub fn refresh(&mut self, library: &[Song]) {
+        if self.shown == Some(library.len()) {
+            return;
+        }
+        let artist = self.artist().map(|artist| artist.name.clone());
+        let album = self.album().map(|album| album.name.clone());
+        let track = self.track().map(|song| song.name.clone());
+
+        self.artists.items = group(library);
+        let i = artist
+            .and_then(|name| self.artists.items.iter().position(|a| a.name == name))
+            .or((!self.artists.items.is_empty()).then_some(0));
+        self.artists.state.select(i);
+        self.fill_albums(album.as_deref(), track.as_deref());
+        // The focused column may be empty now.
+        if self.column == Column::Tracks && self.tracks.items.is_empty() {
+            self.column = Column::Albums;
+        }
+        if self.column == Column::Albums && self.albums.items.is_empty() {
+            self.column = Column::Artists;
+        }
+        self.shown = Some(library.len());
+    }
+
+    /// Show the albums of the selected artist, selecting `album` if it is there or else the first.
+    fn fill_albums(&mut self, album: Option<&str>, track: Option<&str>) {
+        let albums = self
+            .artists
+            .state
+            .selected()
+            .and_then(|i| self.artists.items.get(i))
+            .map(|artist| &artist.albums[..])
+            .unwrap_or_default();
+        let i = album
+            .and_then(|name| albums.iter().position(|a| a.name == name))
+            .or((!albums.is_empty()).then_some(0));
+        self.albums.items = albums.iter().map(|album| album.name.clone()).collect();
+        self.albums.state.select(i);
+        self.fill_tracks(track);
+    }
+
+    /// Show the songs of the selected album, a track is only selected while the column has the
+    /// focus.
+    fn fill_tracks(&mut self, track: Option<&str>) {
+        let songs = self.album().map(|album| album.songs.clone());
+        let songs = songs.unwrap_or_default();
+        let i = track
+            .and_then(|name| songs.iter().position(|song| song.name == name))
+            .or(Some(0))
+            .filter(|_| self.column == Column::Tracks && !songs.is_empty());
+        self.tracks.items = songs;
+        self.tracks.state.select(i);
+    }
+
+    /// Refill the columns on the right of the focused one, its selection moved.
+    fn follow(&mut self) {
+        match self.column {
+            Column::Artists => self.fill_albums(None, None),
+            Column::Albums => self.fill_tracks(None),
+            Column::Tracks => {}
+        }
+    }
+
+    /// Move the focus to the next column on the right, or on the left, returns false if it can
+    /// not move.
+    pub fn focus(&mut self, right: bool) -> bool {
+        let column = match (self.column, right) {
+            (Column::Artists, true) if !self.albums.items.is_empty() => Column::Albums,
+            (Column::Albums, true) if !self.tracks.items.is_empty() => Column::Tracks,
+            (Column::Albums, false) => Column::Artists,
+            (Column::Tracks, false) => Column::Albums,
+            _ => return false,
+        };
+        self.column = column;
+        self.tracks
+            .state
+            .select((column == Column::Tracks).then_some(0));
+        true
+    }
+
+    pub fn previous(&mut self) {
+        match self.column {
+            Column::Artists if !self.artists.items.is_empty() => self.artists.previous(),
+            Column::Albums if !self.albums.items.is_empty() => self.albums.previous(),
+            Column::Tracks if !self.tracks.items.is_empty() => self.tracks.previous(),
+            _ => return,
+        }
+        self.follow();
+    }
+
+    pub fn next(&mut self) {
+        match self.column {
+            Column::Artists if !self.artists.items.is_empty() => self.artists.next(),
+            Column::Albums if !self.albums.items.is_empty() => self.albums.next(),
+            Column::Tracks if !self.tracks.items.is_empty() => self.tracks.next(),
+            _ => return,
+        }
+        self.follow();
+    }
+
+    pub fn first(&mut self) {
+        match self.column {
+            Column::Artists => self.artists.first(),
+            Column::Albums => self.albums.first(),
+            Column::Tracks => self.tracks.first(),
+        }
+        self.follow();
+    }
+
+    pub fn last(&mut self) {
+        match self.column {
+            Column::Artists => self.artists.last(),
+            Column::Albums => self.albums.last(),
+            Column::Tracks => self.tracks.last(),
+        }
+        self.follow();
+    }
+
+    /// The songs of the selected artist, album or track, by the focused column.
+    pub fn selected_songs(&self) -> Vec<Song> {
+        match self.column {
+            Column::Artists => self
+                .artist()
+                .map(|artist| {
+                    artist
+                        .albums
+                        .iter()
+                        .flat_map(|album| album.songs.iter().cloned())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            Column::Albums => self
+                .album()
+                .map(|album| album.songs.clone())
+                .unwrap_or_default(),
+            Column::Tracks => self.track().cloned().into_iter().collect(),
+        }
+    }
+
+    /// The songs to play, the selected track and the rest of its album, or the selected artist or
+    /// album.
+    pub fn songs_to_play(&self) -> Vec<Song> {
+        match self.column {
+            Column::Tracks => {
+                let start = self.tracks.state.selected().unwrap_or(0);
+                self.tracks.items.get(start..).unwrap_or_default().to_vec()
+            }
+            _ => self.selected_songs(),
+        }
+    }
+}
+
+/// Names in natural order, with the placeholder for a missing tag last.
+fn by_name(a: &str, b: &str, unknown: &str) -> std::cmp::Ordering {
+    (a == unknown).cmp(&(b == unknown)).then(natural_cmp(a, b))
+}
+
+/// Group the songs by album artist, or artist if it is missing, then by album.
+fn group(library: &[Song]) -> Vec<Artist> {
+    let mut artists: HashMap<&str, HashMap<&str, Vec<Song>>> = HashMap::new();
+    for song in library {
+        let tags = &song.tags;
+        let artist = tags
+            .album_artist
+            .as_deref()
+            .or(tags.artist.as_deref())
+            .unwrap_or(UNKNOWN_ARTIST);
+        let album = tags.album.as_deref().unwrap_or(UNKNOWN_ALBUM);
+        artists
+            .entry(artist)
+            .or_default()
+            .entry(album)
+            .or_default()
+            .push(song.clone());
+    }
+
+    let by_track = Sort {
+        key: SortKey::Album,
+        descending: false,
+    };
+    let mut artists: Vec<Artist> = artists
+        .into_iter()
+        .map(|(name, albums)| {
+            let mut albums: Vec<Album> = albums
+                .into_iter()
+                .map(|(name, mut songs)| {
+                    songs.sort_by(|a, b| by_track.compare(a, b));
+                    Album {
+                        name: name.to_string(),
+                        songs,
+                    }
+                })
+                .collect();
+            albums.sort_by(|a, b| by_name(&a.name, &b.name, UNKNOWN_ALBUM));
+            Artist {
+                name: name.to_string(),
+                albums,
+            }
+        })
+        .collect();
+    artists.sort_by(|a, b| by_name(&a.name, &b.name, UNKNOWN_ARTIST));
+    artists
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta::Tags;
+    use std::path::PathBuf;
+
+    fn song(name: &str, artist: Option<&str>, album: Option<&str>, track: u32) -> Song {
+        Song {
+            name: name.to_string(),
+            rel_path: PathBuf::from(name),
+            time: 0.0,
+            tags: Tags {
+                artist: artist.map(String::from),
+                album: album.map(String::from),
+                track: Some(track),
+                ..Tags::default()
+            },
+            unplayable: false,
+            added: 0,
+        }
+    }
+
+    fn names(songs: &[Song]) -> Vec<&str> {
+        songs.iter().map(|song| song.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_browser() {
+        let mut library = vec![
+            song("b2", Some("Beta"), Some("Two"), 2),
+            song("x", None, None, 1),
+            song("a1", Some("alpha"), Some("One"), 1),
+            song("b1", Some("Beta"), Some("Two"), 1),
+            song("b3", Some("Beta"), None, 1),
+            song("b4", Some("Beta"), Some("Album 10"), 1),
+            song("b5", Some("Beta"), Some("Album 9"), 1),
+        ];
+        let mut browser = Browser::new();
+        browser.refresh(&library);
+        let artists: Vec<&str> = browser
+            .artists
+            .items
+            .iter()
+            .map(|a| a.name.as_str())
+            .collect();
+        assert_eq!(artists, ["alpha", "Beta", UNKNOWN_ARTIST]);
+        assert_eq!(browser.albums.items, ["One"]);
+        assert_eq!(names(&browser.tracks.items), ["a1"]);
+        assert_eq!(browser.tracks.state.selected(), None);
+
+        browser.next();
+        assert_eq!(
+            browser.albums.items,
+            ["Album 9", "Album 10", "Two", UNKNOWN_ALBUM]
+        );
+        assert_eq!(
+            names(&browser.selected_songs()),
+            ["b5", "b4", "b1", "b2", "b3"]
+        );
+
+        assert!(browser.focus(true));
+        browser.last();
+        browser.previous();
+        assert_eq!(names(&browser.tracks.items), ["b1", "b2"]);
+        assert_eq!(names(&browser.selected_songs()), ["b1", "b2"]);
+
+        assert!(browser.focus(true));
+        assert!(!browser.focus(true));
+        browser.next();
+        assert_eq!(names(&browser.selected_songs()), ["b2"]);
+        browser.previous();
+        assert_eq!(names(&browser.songs_to_play()), ["b1", "b2"]);
+
+        // A rescan keeps the selection.
+        library.push(song("a2", Some("alpha"), Some("One"), 2));
+        browser.invalidate();
+        browser.refresh(&library);
+        assert_eq!(browser.column, Column::Tracks);
+        assert_eq!(names(&browser.selected_songs()), ["b1"]);
+
+        assert!(browser.focus(false) && browser.focus(false));
+        assert!(!browser.focus(false));
+        browser.first();
+        assert_eq!(names(&browser.selected_songs()), ["a1", "a2"]);
+    }
+}
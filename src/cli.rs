@@ -0,0 +1,342 @@
+use clap::{Parser, Subcommand, ValueEnum};
+use color_eyre::{eyre::eyre, Result};
+use std::io::IsTerminal;
+use std::path::PathBuf;
+
+use crate::{
+    app::{parse_timestamp, Song},
+    config::Config,
+    ipc::{self, Request},
+    keymap::Keymap,
+    library::{self, Event},
+    ui::{BROWSER_TAB, FILES_TAB, LIBRARY_TAB, PLAYLIST_TAB, QUEUE_TAB, VISUALIZER_TAB},
+};
+
+/// A terminal music player.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    /// Music directory, overrides `path` of the config file.
+    #[arg(short, long, global = true)]
+    pub music_dir: Option<PathBuf>,
+    /// Config file to read instead of `$XDG_CONFIG_HOME/rmu/config.toml`.
+    #[arg(short, long, global = true)]
+    pub config: Option<PathBuf>,
+    /// Control socket to use instead of `$XDG_RUNTIME_DIR/rmu/rmu.sock`.
+    #[arg(short, long, global = true)]
+    pub socket: Option<PathBuf>,
+    /// Tab shown at startup, the one of the last session by default.
+    #[arg(short, long, value_enum)]
+    pub tab: Option<Tab>,
+    /// Files or directories to play, they are played in order before the library.
+    pub files: Vec<PathBuf>,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Scan the music directory and update the library cache.
+    Scan,
+    /// Print the songs of the library.
+    List {
+        #[arg(short, long, value_enum, default_value_t = Format::Text)]
+        format: Format,
+    },
+    /// Work with the config file.
+    #[command(subcommand)]
+    Config(ConfigCommand),
+    /// Control the running player.
+    #[command(subcommand)]
+    Ctl(CtlCommand),
+}
+
+#[derive(Subcommand, Debug, PartialEq, Eq)]
+pub enum ConfigCommand {
+    /// Check the config file and the music directory, fails if anything is wrong.
+    Check,
+}
+
+#[derive(Subcommand, Debug, PartialEq, Eq)]
+pub enum CtlCommand {
+    /// Resume, or play a file or directory at once.
+    Play { path: Option<PathBuf> },
+    /// Pause the current song.
+    Pause,
+    /// Pause or resume the current song.
+    Toggle,
+    /// Stop playing.
+    Stop,
+    /// Play the next song.
+    Next,
+    /// Play the previous song.
+    Previous,
+    /// Seek to a timestamp like `1:23`, or by `+5` or `-5` seconds.
+    Seek {
+        #[arg(allow_hyphen_values = true)]
+        position: String,
+    },
+    /// Set the volume in percent, or change it by `+5` or `-5`.
+    Volume {
+        #[arg(allow_hyphen_values = true)]
+        level: String,
+    },
+    /// Mute or unmute.
+    Mute,
+    /// Add a file or directory to the end of the queue.
+    Enqueue { path: PathBuf },
+    /// Print the state of the player as JSON.
+    Status,
+}
+
+impl CtlCommand {
+    /// The request sent to the player, paths are made absolute since the player may run
+    /// elsewhere.
+    fn request(&self) -> Result<Request> {
+        let relative = |s: &str| s.starts_with(['+', '-']);
+        Ok(match self {
+            CtlCommand::Play { path } => Request::Play {
+                path: path.as_deref().map(std::path::absolute).transpose()?,
+            },
+            CtlCommand::Pause => Request::Pause,
+            CtlCommand::Toggle => Request::Toggle,
+            CtlCommand::Stop => Request::Stop,
+            CtlCommand::Next => Request::Next,
+            CtlCommand::Previous => Request::Previous,
+            CtlCommand::Seek { position } if relative(position) => Request::Seek {
+                position: None,
+                offset: Some(
+                    position
+                        .parse()
+                        .map_err(|_| eyre!("Invalid offset `{}`", position))?,
+                ),
+            },
+            CtlCommand::Seek { position } => Request::Seek {
+                position: Some(
+                    parse_timestamp(position)
+                        .ok_or_else(|| eyre!("Invalid timestamp `{}`", position))?
+                        .as_secs_f64(),
+                ),
+                offset: None,
+            },
+            CtlCommand::Volume { level } if relative(level) => Request::Volume {
+                level: None,
+                change: Some(
+                    level
+                        .parse()
+                        .map_err(|_| eyre!("Invalid change `{}`", level))?,
+                ),
+            },
+            CtlCommand::Volume { level } => Request::Volume {
+                level: Some(
+                    level
+                        .parse()
+                        .map_err(|_| eyre!("Invalid level `{}`", level))?,
+                ),
+                change: None,
+            },
+            CtlCommand::Mute => Request::Mute,
+            CtlCommand::Enqueue { path } => Request::Enqueue {
+                path: std::path::absolute(path)?,
+            },
+            CtlCommand::Status => Request::Status,
+        })
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tab {
+    Library,
+    Visualizer,
+    Queue,
+    Playlists,
+    Browser,
+    Files,
+}
+
+impl Tab {
+    pub fn index(self) -> usize {
+        match self {
+            Tab::Library => LIBRARY_TAB,
+            Tab::Visualizer => VISUALIZER_TAB,
+            Tab::Queue => QUEUE_TAB,
+            Tab::Playlists => PLAYLIST_TAB,
+            Tab::Browser => BROWSER_TAB,
+            Tab::Files => FILES_TAB,
+        }
+    }
+}
+
+/// How `list` prints the songs.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// One path a line.
+    Text,
+    /// An array of songs with their tags.
+    Json,
+}
+
+impl Cli {
+    /// Load the config with the overrides of the command line. When there is no config file at
+    /// all, the music directory is asked for if `interactive` is set.
+    pub async fn load_config(&self, interactive: bool) -> Result<Config> {
+        let mut config = match &self.config {
+            Some(path) => Config::read(path).await?,
+            None => {
+                let path = Config::path()?;
+                if path.exists() {
+                    Config::read(&path).await?
+                } else if self.music_dir.is_some() {
+                    Config::default()
+                } else if interactive {
+                    Config::new().await?
+                } else {
+                    return Err(eyre!(
+                        "No config file `{}`, create it or pass --music-dir",
+                        path.display()
+                    ));
+                }
+            }
+        };
+        if let Some(dir) = &self.music_dir {
+            config.path = dir.to_string_lossy().to_string();
+        }
+        Ok(config)
+    }
+
+    /// The control socket of the player.
+    pub fn socket_path(&self) -> Result<PathBuf> {
+        self.socket.clone().map_or_else(ipc::socket_path, Ok)
+    }
+
+    /// Run the subcommand, nothing is asked for.
+    pub async fn run(&self, command: &Command) -> Result<()> {
+        match command {
+            Command::Scan => {
+                let config = self.load_config(false).await?;
+                let progress = std::io::stderr().is_terminal();
+                let (mut total, mut done, mut unplayable) = (0, 0, 0);
+                library::scan(&config, &library::cache_path()?, |event| match event {
+                    Event::Total(n) => total = n,
+                    Event::Song(song) => {
+                        done += 1;
+                        unplayable += usize::from(song.unplayable);
+                        if progress {
+                            eprint!("\rScanning {}/{} files", done, total);
+                        }
+                    }
+                    Event::Done(_) => {}
+                })?;
+                if progress {
+                    eprintln!();
+                }
+                println!("{} songs, {} unplayable", done, unplayable);
+            }
+            Command::List { format } => {
+                let songs = scan_songs(&self.load_config(false).await?)?;
+                match format {
+                    Format::Text => songs.iter().for_each(|song| println!("{}", song.name)),
+                    Format::Json => println!("{}", serde_json::to_string_pretty(&songs)?),
+                }
+            }
+            Command::Config(ConfigCommand::Check) => {
+                let config = self.load_config(false).await?;
+                let mut problems = Vec::new();
+                if let Err(e) = Keymap::new(&config.keys) {
+                    problems.push(format!("Invalid key binding: {}", e));
+                }
+                if let Err(e) = std::fs::read_dir(&config.path) {
+                    problems.push(format!(
+                        "Can not read the music directory `{}`: {}",
+                        config.path, e
+                    ));
+                }
+                if !problems.is_empty() {
+                    return Err(eyre!(problems.join("\n")));
+                }
+                println!("The config is valid");
+            }
+            // The player has its own config, `ctl` does not need one.
+            Command::Ctl(command) => {
+                let response = ipc::send(&self.socket_path()?, &command.request()?).await?;
+                if let Some(error) = response.error {
+                    return Err(eyre!(error));
+                }
+                if let Some(status) = response.status {
+                    println!("{}", serde_json::to_string_pretty(&status)?);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// All songs of the library, unchanged files are read from the cache.
+fn scan_songs(config: &Config) -> Result<Vec<Song>> {
+    let mut songs = Vec::new();
+    library::scan(config, &library::cache_path()?, |event| {
+        if let Event::Song(song) = event {
+            songs.push(*song);
+        }
+    })?;
+    Ok(songs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let cli = Cli::try_parse_from(["rmu", "-t", "queue", "a.mp3", "dir"]).unwrap();
+        assert_eq!(cli.tab.map(Tab::index), Some(QUEUE_TAB));
+        assert_eq!(cli.files, [PathBuf::from("a.mp3"), PathBuf::from("dir")]);
+        assert_eq!(cli.command, None);
+
+        let cli = Cli::try_parse_from(["rmu", "list", "--format", "json", "-m", "/music"]).unwrap();
+        assert_eq!(
+            cli.command,
+            Some(Command::List {
+                format: Format::Json
+            })
+        );
+        assert_eq!(cli.music_dir, Some(PathBuf::from("/music")));
+
+        let cli = Cli::try_parse_from(["rmu", "config", "check"]).unwrap();
+        assert_eq!(cli.command, Some(Command::Config(ConfigCommand::Check)));
+        assert!(Cli::try_parse_from(["rmu", "--tab", "nowhere"]).is_err());
+
+        let request = |args: &[&str]| match Cli::try_parse_from(args).unwrap().command {
+            Some(Command::Ctl(command)) => command.request().unwrap(),
+            command => panic!("{:?}", command),
+        };
+        assert_eq!(
+            request(&["rmu", "ctl", "seek", "-5"]),
+            Request::Seek {
+                position: None,
+                offset: Some(-5.0)
+            }
+        );
+        assert_eq!(
+            request(&["rmu", "ctl", "seek", "1:30"]),
+            Request::Seek {
+                position: Some(90.0),
+                offset: None
+            }
+        );
+        assert_eq!(
+            request(&["rmu", "ctl", "volume", "+10"]),
+            Request::Volume {
+                level: None,
+                change: Some(10)
+            }
+        );
+        assert_eq!(
+            request(&["rmu", "-s", "/tmp/rmu.sock", "ctl", "volume", "40"]),
+            Request::Volume {
+                level: Some(40),
+                change: None
+            }
+        );
+    }
+}
@@ -1,68 +1,207 @@
-use color_eyre::Result;
-use rodio::{Decoder, Source};
+use color_eyre::{eyre::eyre, Result};
 use serde_derive::Deserialize;
-use std::fs::File;
-use std::io::BufReader;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
-use std::time::Duration;
 use tokio::fs;
 
-use crate::app::Song;
+use crate::format;
 use crate::input::Input;
+use crate::mode::Repeat;
+use crate::replaygain::ReplayGainConfig;
+use crate::sort::Sort;
+use crate::visualizer::VisualizerConfig;
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Clone, Default)]
 pub struct Config {
-    path: String,
+    pub path: String,
+    /// The file the config was read from.
+    #[serde(skip)]
+    pub file: Option<PathBuf>,
+    /// The library cache, `library::cache_path` if unset.
+    #[serde(skip)]
+    pub cache: Option<PathBuf>,
+    /// How to walk the music directory.
+    #[serde(default)]
+    pub scan: Scan,
+    /// Key bindings, maps key sequences to actions.
+    #[serde(default)]
+    pub keys: HashMap<String, String>,
+    #[serde(default)]
+    pub visualizer: VisualizerConfig,
+    #[serde(default)]
+    pub playback: Playback,
+    #[serde(default)]
+    pub replaygain: ReplayGainConfig,
+    #[serde(default)]
+    pub sort: Sort,
+}
+
+/// The `[scan]` table, controls how the music directory is walked.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Scan {
+    /// Maximum directory depth to descend, `1` only reads the top level. Unlimited if unset.
+    pub max_depth: Option<usize>,
+    /// Follow symbolic links to files and directories.
+    pub follow_links: bool,
+    /// Skip files and directories whose name starts with a dot.
+    pub skip_hidden: bool,
+    /// Extensions always added to the library, matched case-insensitively. Files with any other
+    /// extension are added only if their header looks like a supported format.
+    pub extensions: Vec<String>,
+    /// Extensions never added to the library, even if the header looks like a supported format.
+    pub exclude: Vec<String>,
+    /// Watch the music directory, songs added, removed or changed show up without a rescan.
+    pub watch: bool,
+}
+
+impl Default for Scan {
+    fn default() -> Self {
+        Self {
+            max_depth: None,
+            follow_links: false,
+            skip_hidden: true,
+            extensions: format::EXTENSIONS
+                .iter()
+                .map(|ext| ext.to_string())
+                .collect(),
+            exclude: Vec::new(),
+            watch: true,
+        }
+    }
+}
+
+/// The `[playback]` table.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Playback {
+    pub shuffle: bool,
+    /// Seed of the shuffle order, the same seed gives the same order. Random if unset.
+    pub shuffle_seed: Option<u64>,
+    pub repeat: Repeat,
+    /// Seconds of a short and a long relative seek.
+    pub seek_step: f64,
+    pub seek_step_long: f64,
+    /// Seconds the end of a song overlaps the start of the next one, gapless if zero.
+    pub crossfade: f64,
+    /// Also crossfade between songs of the same album, they are gapless by default.
+    pub crossfade_same_album: bool,
+    /// Seconds of the fade when pausing, resuming and stopping, cut at once if zero.
+    pub fade: f64,
+    /// Percent the volume changes by.
+    pub volume_step: u8,
+    /// Restore the song, position, queue, modes and tab of the last session at startup.
+    pub resume: bool,
+}
+
+impl Default for Playback {
+    fn default() -> Self {
+        Self {
+            shuffle: false,
+            shuffle_seed: None,
+            repeat: Repeat::Off,
+            seek_step: 5.0,
+            seek_step_long: 30.0,
+            crossfade: 0.0,
+            crossfade_same_album: false,
+            fade: 0.0,
+            volume_step: 5,
+            resume: true,
+        }
+    }
 }
 
 impl Config {
-    pub async fn new() -> Result<Self> {
-        let crate_name = env!("CARGO_CRATE_NAME");
-
-        let mut config_path = if let Ok(config_dir) = std::env::var("XDG_CONFIG_HOME") {
-            PathBuf::from(config_dir)
-        } else if let Ok(home_dir) = std::env::var("HOME") {
-            let mut path = PathBuf::from(home_dir);
-            path.push(".config");
-            path
-        } else {
-            panic!("Neither XDG_CONFIG_HOME nor HOME environment variables are set");
+    /// The default config file, `$XDG_CONFIG_HOME/rmu/config.toml`.
+    pub fn path() -> Result<PathBuf> {
+        let mut path = xdg_dir("XDG_CONFIG_HOME", ".config")?;
+        path.push("config.toml");
+        Ok(path)
+    }
+
+    /// Read and parse the config file.
+    pub async fn read(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .await
+            .map_err(|e| eyre!("Can not read `{}`: {}", path.display(), e))?;
+        let mut config: Config = toml::from_str(&contents)
+            .map_err(|e| eyre!("Invalid config `{}`: {}", path.display(), e))?;
+        config.path = expand_var(&config.path);
+        config.file = Some(path.to_path_buf());
+        Ok(config)
+    }
+
+    /// Make `dir` the music directory and write it to the config file, its other settings and
+    /// comments are kept. The default file is created if the config was not read from one.
+    pub fn set_path(&mut self, dir: &Path) -> Result<()> {
+        let file = match &self.file {
+            Some(file) => file.clone(),
+            None => Self::path()?,
         };
-        config_path.push(crate_name);
-        config_path.push("config.toml");
-
-        match fs::read_to_string(&config_path).await {
-            Ok(contents) => {
-                let mut config: Config = toml::from_str(&contents)?;
-                config.path = expand_var(&config.path);
-                Ok(config)
-            }
-            Err(_) => {
-                let path = Path::new(&config_path);
-                if !path.exists() {
-                    if let Some(parent) = path.parent() {
-                        fs::create_dir_all(parent).await?;
-                    }
-                }
-
-                // In this case, create a box to input config file path.
-                let mut input = Input::new();
-                match input.run() {
-                    Ok(_) => {
-                        let content = format!("path = \"{}\"", input.path);
-                        fs::write(config_path, content.as_bytes()).await?;
-                        let path = expand_var(&input.path);
-                        Ok(Self { path })
-                    }
-                    Err(e) => Err(e),
-                }
-            }
+        write_path(&file, &dir.to_string_lossy())?;
+        self.path = dir.to_string_lossy().to_string();
+        self.file = Some(file);
+        Ok(())
+    }
+
+    /// Read the default config file, the music directory is asked for if it is missing.
+    pub async fn new() -> Result<Self> {
+        let config_path = Self::path()?;
+        if config_path.exists() {
+            return Self::read(&config_path).await;
+        }
+        if let Some(parent) = config_path.parent() {
+            fs::create_dir_all(parent).await?;
         }
+
+        // In this case, create a box to input config file path.
+        let mut input = Input::new();
+        input.run()?;
+        let content = format!("path = \"{}\"", input.path);
+        fs::write(&config_path, content.as_bytes()).await?;
+        Self::read(&config_path).await
+    }
+}
+
+/// Set `path` in the config file, it is created if missing.
+fn write_path(file: &Path, path: &str) -> Result<()> {
+    let contents = match std::fs::read_to_string(file) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(eyre!("Can not read `{}`: {}", file.display(), e)),
+    };
+    let mut doc: toml_edit::Document = contents
+        .parse()
+        .map_err(|e| eyre!("Invalid config `{}`: {}", file.display(), e))?;
+    doc["path"] = toml_edit::value(path);
+    if let Some(parent) = file.parent() {
+        std::fs::create_dir_all(parent)?;
     }
+    std::fs::write(file, doc.to_string())
+        .map_err(|e| eyre!("Can not write `{}`: {}", file.display(), e))
+}
+
+/// Get the directory of this crate under the XDG base directory `var`, falls back to
+/// `$HOME/fallback` when `var` is not set.
+pub fn xdg_dir(var: &str, fallback: &str) -> Result<PathBuf> {
+    let mut path = if let Ok(dir) = std::env::var(var) {
+        PathBuf::from(dir)
+    } else if let Ok(home_dir) = std::env::var("HOME") {
+        let mut path = PathBuf::from(home_dir);
+        path.push(fallback);
+        path
+    } else {
+        return Err(eyre!(
+            "Neither {} nor HOME environment variables are set",
+            var
+        ));
+    };
+    path.push(env!("CARGO_CRATE_NAME"));
+    Ok(path)
 }
 
 /// Replace environment variable.
-fn expand_var(path: &str) -> String {
+pub fn expand_var(path: &str) -> String {
     // Expand `~` variable.
     if path.starts_with('~') {
         if let Ok(home) = std::env::var("HOME") {
@@ -72,45 +211,30 @@ fn expand_var(path: &str) -> String {
     path.to_string()
 }
 
-pub async fn playlist() -> Result<Vec<Song>> {
-    let mut playlist = Vec::new();
-    let config = Config::new().await.unwrap();
-
-    // Get all songs name
-    let mut dir = fs::read_dir(config.path).await.unwrap();
-    while let Ok(entry) = dir.next_entry().await {
-        match entry {
-            Some(entry) => {
-                let path = entry.path();
-                if let Some(extension) = path.extension() {
-                    if extension == "mp3" || extension == "mp4" || extension == "wav" {
-                        let file = File::open(&path)?; // Replace with your file path
-                        let source = Decoder::new(BufReader::new(file)).unwrap();
-                        let total_duration =
-                            source.total_duration().unwrap_or(Duration::from_secs(0));
-                        let time = total_duration.as_secs_f64();
-                        playlist.push(Song {
-                            name: String::from(path.to_str().unwrap()),
-                            time,
-                        });
-                    }
-                }
-            }
-            None => break,
-        }
-    }
-    Ok(playlist)
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::testing;
 
-    #[tokio::test]
-    async fn test_playlist() {
-        let entries = playlist().await;
-        for entry in entries.unwrap() {
-            println!("{:?}", entry);
-        }
+    #[test]
+    fn test_write_path() {
+        let dir = testing::temp_dir("config");
+        let file = dir.join("config.toml");
+        std::fs::write(
+            &file,
+            "# My music\npath = \"~/Music\"\n\n[playback]\nrepeat = \"all\" # loop\n",
+        )
+        .unwrap();
+        write_path(&file, "/data/music").unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&file).unwrap(),
+            "# My music\npath = \"/data/music\"\n\n[playback]\nrepeat = \"all\" # loop\n"
+        );
+
+        let file = dir.join("new/config.toml");
+        write_path(&file, "/data/music").unwrap();
+        let config: Config = toml::from_str(&std::fs::read_to_string(&file).unwrap()).unwrap();
+        assert_eq!(config.path, "/data/music");
+        std::fs::remove_dir_all(dir).unwrap();
     }
 }
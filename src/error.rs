@@ -0,0 +1,21 @@
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// Errors shown in the status line instead of stopping the player.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Can not open `{}`: {source}", path.display())]
+    Open { path: PathBuf, source: io::Error },
+    #[error("Can not decode `{}`: {source}", path.display())]
+    Decode {
+        path: PathBuf,
+        source: rodio::decoder::DecoderError,
+    },
+    #[error("Can not read the music directory `{}`: {source}", path.display())]
+    MusicDir { path: PathBuf, source: io::Error },
+    #[error("Can not open the audio output: {0}")]
+    Output(#[from] rodio::StreamError),
+    #[error("Can not play on the audio output: {0}")]
+    Play(#[from] rodio::PlayError),
+}
@@ -0,0 +1,159 @@
+use rodio::{source::SeekError, Sample, Source};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// An `f32` shared with the audio thread.
+#[derive(Default)]
+struct AtomicF32(AtomicU32);
+
+impl AtomicF32 {
+    fn new(value: f32) -> Self {
+        Self(AtomicU32::new(value.to_bits()))
+    }
+
+    fn load(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    fn store(&self, value: f32) {
+        self.0.store(value.to_bits(), Ordering::Relaxed)
+    }
+}
+
+#[derive(Default)]
+struct Ramp {
+    /// Gain applied to the samples right now.
+    gain: AtomicF32,
+    /// Gain to reach.
+    target: AtomicF32,
+    /// How much the gain moves in a second.
+    rate: AtomicF32,
+}
+
+/// `Fader` controls the gain of all sources wrapped by it, the gain moves smoothly from sample to
+/// sample so there are no clicks.
+#[derive(Clone)]
+pub struct Fader {
+    ramp: Arc<Ramp>,
+}
+
+impl Fader {
+    pub fn new(gain: f32) -> Self {
+        Self {
+            ramp: Arc::new(Ramp {
+                gain: AtomicF32::new(gain),
+                target: AtomicF32::new(gain),
+                rate: AtomicF32::default(),
+            }),
+        }
+    }
+
+    /// Move the gain to `target` in `secs` seconds, at once if `secs` is zero.
+    pub fn fade_to(&self, target: f32, secs: f64) {
+        if secs <= 0.0 {
+            self.ramp.gain.store(target);
+        } else {
+            let distance = (target - self.ramp.gain.load()).abs();
+            self.ramp.rate.store(distance / secs as f32);
+        }
+        self.ramp.target.store(target);
+    }
+
+    /// Whether the gain has dropped to zero.
+    pub fn is_silent(&self) -> bool {
+        self.ramp.gain.load() <= 0.0
+    }
+
+    /// Wrap the source so its gain is controlled by this fader.
+    pub fn wrap<S>(&self, source: S) -> Fade<S>
+    where
+        S: Source,
+        S::Item: Sample,
+    {
+        Fade {
+            inner: source,
+            ramp: self.ramp.clone(),
+        }
+    }
+}
+
+/// `Fade` applies the gain of its `Fader` to the samples of a source.
+pub struct Fade<S> {
+    inner: S,
+    ramp: Arc<Ramp>,
+}
+
+impl<S> Iterator for Fade<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    type Item = S::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = self.inner.next()?;
+        let mut gain = self.ramp.gain.load();
+        let target = self.ramp.target.load();
+        if gain != target {
+            let samples_per_sec = self.inner.sample_rate() as f32 * self.inner.channels() as f32;
+            let step = self.ramp.rate.load() / samples_per_sec.max(1.0);
+            gain = if gain < target {
+                (gain + step).min(target)
+            } else {
+                (gain - step).max(target)
+            };
+            self.ramp.gain.store(gain);
+        }
+        Some(sample.amplify(gain))
+    }
+}
+
+impl<S> Source for Fade<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::buffer::SamplesBuffer;
+
+    #[test]
+    fn test_fade() {
+        let fader = Fader::new(1.0);
+        let mut source = fader.wrap(SamplesBuffer::new(1, 100, vec![1.0f32; 300]));
+        assert_eq!(source.next(), Some(1.0));
+
+        // Half a second at 100 samples a second.
+        fader.fade_to(0.0, 0.5);
+        let faded: Vec<f32> = source.by_ref().take(60).collect();
+        assert!(faded[..40].windows(2).all(|w| w[1] < w[0]));
+        assert!(faded[55..].iter().all(|&s| s == 0.0));
+        assert!(fader.is_silent());
+
+        fader.fade_to(1.0, 0.0);
+        assert_eq!(source.next(), Some(1.0));
+    }
+}
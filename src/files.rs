@@ -0,0 +1,287 @@
+use color_eyre::{eyre::eyre, Result};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tokio::sync::oneshot;
+
+use crate::{app::Song, config::Scan, library, sort::natural_cmp, ui::StatefulList};
+
+/// An entry of the directory shown in the files tab.
+pub enum Entry {
+    Dir(PathBuf),
+    Song(Box<Song>),
+    /// An audio file outside the library, shown until it is read in background.
+    Loading(PathBuf),
+}
+
+impl Entry {
+    pub fn path(&self) -> &Path {
+        match self {
+            Entry::Dir(path) | Entry::Loading(path) => path,
+            Entry::Song(song) => Path::new(&song.name),
+        }
+    }
+
+    /// The file name, directories end with a slash.
+    pub fn name(&self) -> String {
+        let name = self
+            .path()
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        match self {
+            Entry::Dir(_) => format!("{}/", name),
+            Entry::Song(_) | Entry::Loading(_) => name,
+        }
+    }
+}
+
+/// `Files` browses the filesystem, showing the subdirectories and audio files of a directory.
+pub struct Files {
+    /// The directory shown.
+    pub dir: PathBuf,
+    pub entries: StatefulList<Entry>,
+    /// Whether `dir` was read, it is read the first time the tab is shown.
+    read: bool,
+    /// The songs of the `Loading` entries, read in background.
+    reading: Option<oneshot::Receiver<Vec<Song>>>,
+}
+
+impl Files {
+    pub fn new(dir: PathBuf) -> Self {
+        Self {
+            dir,
+            entries: StatefulList::with_items(Vec::new()),
+            read: false,
+            reading: None,
+        }
+    }
+
+    pub fn selected(&self) -> Option<&Entry> {
+        self.entries.items.get(self.entries.state.selected()?)
+    }
+
+    /// Read the directory the first time the tab is shown.
+    pub fn refresh(&mut self, library: &[Song], scan: &Scan) -> Result<()> {
+        if self.read {
+            return Ok(());
+        }
+        self.read = true;
+        let dir = self.dir.clone();
+        self.open(&dir, library, scan)
+    }
+
+    /// Show `dir`, directories first then audio files, both in natural order. Songs of the
+    /// library keep their tags, others are read from the file in background.
+    fn open(&mut self, dir: &Path, library: &[Song], scan: &Scan) -> Result<()> {
+        let read =
+            fs::read_dir(dir).map_err(|e| eyre!("Can not read `{}`: {}", dir.display(), e))?;
+        let by_name: HashMap<&str, &Song> = library
+            .iter()
+            .map(|song| (song.name.as_str(), song))
+            .collect();
+        let (mut dirs, mut files): (Vec<_>, Vec<_>) = read
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                let hidden = path
+                    .file_name()
+                    .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+                !(scan.skip_hidden && hidden)
+            })
+            .partition(|path| path.is_dir());
+        dirs.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+        files.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+
+        let songs: Vec<Entry> = files
+            .into_iter()
+            .filter(|path| path.is_file() && library::is_audio(path, scan))
+            .map(|path| match by_name.get(path.to_string_lossy().as_ref()) {
+                Some(&song) => Entry::Song(Box::new(song.clone())),
+                None => Entry::Loading(path),
+            })
+            .collect();
+        let loading: Vec<PathBuf> = songs
+            .iter()
+            .filter(|entry| matches!(entry, Entry::Loading(_)))
+            .map(|entry| entry.path().to_path_buf())
+            .collect();
+        // The songs of the directory left are not needed anymore.
+        self.reading = (!loading.is_empty()).then(|| library::spawn_read(loading));
+        self.entries.items = dirs.into_iter().map(Entry::Dir).chain(songs).collect();
+        let came_from = self
+            .entries
+            .items
+            .iter()
+            .position(|entry| entry.path() == self.dir && matches!(entry, Entry::Dir(_)));
+        self.entries
+            .state
+            .select(came_from.or((!self.entries.items.is_empty()).then_some(0)));
+        self.dir = dir.to_path_buf();
+        Ok(())
+    }
+
+    /// Open the selected directory, returns false if a song is selected.
+    pub fn enter(&mut self, library: &[Song], scan: &Scan) -> Result<bool> {
+        let Some(Entry::Dir(dir)) = self.selected() else {
+            return Ok(false);
+        };
+        let dir = dir.clone();
+        self.open(&dir, library, scan)?;
+        Ok(true)
+    }
+
+    /// Open the parent directory, the one left stays selected. Returns false at the root.
+    pub fn parent(&mut self, library: &[Song], scan: &Scan) -> Result<bool> {
+        let Some(parent) = self.dir.parent().map(Path::to_path_buf) else {
+            return Ok(false);
+        };
+        self.open(&parent, library, scan)?;
+        Ok(true)
+    }
+
+    /// Replace the `Loading` entries with the songs read in background, files gone since are
+    /// dropped.
+    pub fn poll(&mut self) {
+        let Some(rx) = &mut self.reading else {
+            return;
+        };
+        let songs = match rx.try_recv() {
+            Ok(songs) => songs,
+            Err(oneshot::error::TryRecvError::Empty) => return,
+            Err(oneshot::error::TryRecvError::Closed) => Vec::new(),
+        };
+        self.reading = None;
+        let mut by_name: HashMap<String, Song> = songs
+            .into_iter()
+            .map(|song| (song.name.clone(), song))
+            .collect();
+        let entries = std::mem::take(&mut self.entries.items);
+        self.entries.items = entries
+            .into_iter()
+            .filter_map(|entry| match entry {
+                Entry::Loading(path) => by_name
+                    .remove(path.to_string_lossy().as_ref())
+                    .map(|song| Entry::Song(Box::new(song))),
+                entry => Some(entry),
+            })
+            .collect();
+        let len = self.entries.items.len();
+        let selected = self.entries.state.selected();
+        self.entries
+            .state
+            .select(selected.filter(|_| len > 0).map(|i| i.min(len - 1)));
+    }
+
+    /// Read the selected song, or all the songs under the selected directory, in background. To
+    /// play, the songs after the selected one are read too. `None` if nothing is selected.
+    pub fn read_selected(
+        &self,
+        scan: &Scan,
+        play: bool,
+    ) -> Option<oneshot::Receiver<Result<Vec<Song>>>> {
+        let start = self.entries.state.selected()?;
+        let (tx, rx) = oneshot::channel();
+        if let Entry::Dir(dir) = self.entries.items.get(start)? {
+            let (dir, scan) = (dir.clone(), scan.clone());
+            tokio::task::spawn_blocking(move || {
+                let _ = tx.send(library::read_paths(&[dir], &scan));
+            });
+            return Some(rx);
+        }
+        let end = match play {
+            true => self.entries.items.len(),
+            false => start + 1,
+        };
+        // The songs already read are kept, the others are read like when they are listed.
+        let entries: Vec<(PathBuf, Option<Song>)> = self.entries.items[start..end]
+            .iter()
+            .filter_map(|entry| match entry {
+                Entry::Dir(_) => None,
+                Entry::Song(song) => Some((PathBuf::from(&song.name), Some(song.as_ref().clone()))),
+                Entry::Loading(path) => Some((path.clone(), None)),
+            })
+            .collect();
+        tokio::task::spawn_blocking(move || {
+            let songs = entries
+                .into_iter()
+                .map(|(path, song)| song.unwrap_or_else(|| library::read_song(&path)))
+                .collect();
+            let _ = tx.send(Ok(songs));
+        });
+        Some(rx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn names(files: &Files) -> Vec<String> {
+        files.entries.items.iter().map(Entry::name).collect()
+    }
+
+    fn song_names(songs: &[Song]) -> Vec<String> {
+        songs
+            .iter()
+            .map(|song| song.rel_path.to_string_lossy().to_string())
+            .collect()
+    }
+
+    /// Wait for the songs of the directory shown.
+    async fn loaded(files: &mut Files) {
+        while files.reading.is_some() {
+            files.poll();
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        }
+    }
+
+    async fn read_selected(files: &Files, scan: &Scan, play: bool) -> Vec<String> {
+        let rx = files.read_selected(scan, play).unwrap();
+        song_names(&rx.await.unwrap().unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_files() {
+        let dir = testing::temp_dir("files");
+        fs::create_dir_all(dir.join("Album 10")).unwrap();
+        fs::create_dir_all(dir.join("Album 9/CD1")).unwrap();
+        fs::create_dir_all(dir.join(".hidden")).unwrap();
+        testing::write_wav(&dir.join("b.wav"), 1);
+        testing::write_wav(&dir.join("a.wav"), 2);
+        testing::write_wav(&dir.join("Album 9/CD1/1.wav"), 1);
+        testing::write_wav(&dir.join("Album 9/2.wav"), 1);
+        fs::write(dir.join("notes.txt"), "not audio").unwrap();
+
+        let scan = Scan::default();
+        let mut files = Files::new(dir.clone());
+        files.refresh(&[], &scan).unwrap();
+        assert_eq!(names(&files), ["Album 9/", "Album 10/", "a.wav", "b.wav"]);
+        assert!(matches!(files.entries.items[2], Entry::Loading(_)));
+        loaded(&mut files).await;
+        let Some(Entry::Song(song)) = files.entries.items.get(2) else {
+            panic!("a.wav is not a song");
+        };
+        assert_eq!(song.time, 2.0);
+
+        assert_eq!(
+            read_selected(&files, &scan, false).await,
+            ["Album 9/2.wav", "Album 9/CD1/1.wav"]
+        );
+        files.entries.state.select(Some(2));
+        assert!(!files.enter(&[], &scan).unwrap());
+        assert_eq!(read_selected(&files, &scan, true).await, ["a.wav", "b.wav"]);
+        assert_eq!(read_selected(&files, &scan, false).await, ["a.wav"]);
+
+        files.entries.state.select(Some(0));
+        assert!(files.enter(&[], &scan).unwrap());
+        assert_eq!(files.dir, dir.join("Album 9"));
+        assert_eq!(names(&files), ["CD1/", "2.wav"]);
+        assert!(files.parent(&[], &scan).unwrap());
+        assert_eq!(files.dir, dir);
+        assert_eq!(files.entries.state.selected(), Some(0));
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,69 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// Extensions of all formats the decoder can play.
+pub const EXTENSIONS: &[&str] = &[
+    "mp3", "mp4", "m4a", "m4b", "aac", "wav", "flac", "ogg", "oga", "aif", "aiff", "aifc",
+];
+
+/// Enough bytes to tell every supported container apart, Ogg needs the first packet header.
+const HEADER_LEN: usize = 36;
+
+/// Guess the format from the first bytes of a file, returns the usual extension.
+pub fn detect(header: &[u8]) -> Option<&'static str> {
+    match header {
+        [b'I', b'D', b'3', ..] => Some("mp3"),
+        [b'f', b'L', b'a', b'C', ..] => Some("flac"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => Some("wav"),
+        [b'F', b'O', b'R', b'M', _, _, _, _, b'A', b'I', b'F', b'F' | b'C', ..] => Some("aiff"),
+        [_, _, _, _, b'f', b't', b'y', b'p', ..] => Some("m4a"),
+        // Only Vorbis can be decoded, Opus and other codecs in Ogg are not supported.
+        [b'O', b'g', b'g', b'S', ..] => {
+            (header.get(28..35) == Some(b"\x01vorbis".as_slice())).then_some("ogg")
+        }
+        // ADTS frame sync, the layer bits are always zero.
+        [0xff, b, ..] if b & 0xf6 == 0xf0 => Some("aac"),
+        // MPEG audio frame sync, any layer but the reserved one.
+        [0xff, b, ..] if b & 0xe0 == 0xe0 && b & 0x06 != 0 => Some("mp3"),
+        _ => None,
+    }
+}
+
+/// Read the header of the file and guess its format.
+pub fn sniff(path: &Path) -> Option<&'static str> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    File::open(path)
+        .ok()?
+        .take(HEADER_LEN as u64)
+        .read_to_end(&mut header)
+        .ok()?;
+    detect(&header)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect(b"ID3\x04\x00"), Some("mp3"));
+        assert_eq!(detect(&[0xff, 0xfb, 0x90, 0x00]), Some("mp3"));
+        assert_eq!(detect(&[0xff, 0xf1, 0x50, 0x80]), Some("aac"));
+        assert_eq!(detect(b"fLaC\x00\x00\x00\x22"), Some("flac"));
+        assert_eq!(detect(b"RIFF\x24\x08\x00\x00WAVEfmt "), Some("wav"));
+        assert_eq!(detect(b"FORM\x00\x00\x00\x00AIFC"), Some("aiff"));
+        assert_eq!(detect(b"\x00\x00\x00\x20ftypM4A "), Some("m4a"));
+
+        let mut ogg = b"OggS".to_vec();
+        ogg.resize(28, 0);
+        let mut opus = ogg.clone();
+        ogg.extend_from_slice(b"\x01vorbis");
+        opus.extend_from_slice(b"OpusHead");
+        assert_eq!(detect(&ogg), Some("ogg"));
+        assert_eq!(detect(&opus), None);
+
+        assert_eq!(detect(b"\x89PNG\r\n"), None);
+        assert_eq!(detect(b""), None);
+    }
+}
@@ -1,7 +1,7 @@
 use color_eyre::Result;
 use crossterm::{
     cursor::{CursorShape, SetCursorShape},
-    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
     execute,
 };
 use ratatui::{
@@ -15,6 +15,16 @@ use unicode_width::UnicodeWidthChar;
 
 use crate::term::Term;
 
+/// What a key press did to the input.
+pub enum InputEvent {
+    /// The text or the cursor changed.
+    Edit,
+    /// Enter was pressed, the text is submitted.
+    Submit,
+    /// Esc was pressed.
+    Cancel,
+}
+
 /// Input structure hold the state about the config file input.
 pub struct Input {
     /// Current value of the input box.
@@ -115,6 +125,53 @@ impl Input {
         self.reset_cursor();
     }
 
+    /// Replace the text, the cursor goes to its end.
+    pub fn set_value(&mut self, value: &str) {
+        self.input = value.to_string();
+        self.move_cursor_end();
+    }
+
+    /// Current text of the input box.
+    pub fn value(&self) -> &str {
+        &self.input
+    }
+
+    /// Display width of the text before the cursor.
+    pub fn cursor_width(&self) -> u16 {
+        self.input
+            .chars()
+            .take(self.char_idx)
+            .map(|c| UnicodeWidthChar::width(c).unwrap_or(0))
+            .sum::<usize>() as u16
+    }
+
+    /// Edit the input with the key, the text is moved to `path` on submit.
+    pub fn handle_key(&mut self, key: KeyEvent) -> InputEvent {
+        match (key.code, key.modifiers) {
+            (KeyCode::Char('b'), KeyModifiers::CONTROL) | (KeyCode::Left, _) => {
+                self.move_cursor_left()
+            }
+            (KeyCode::Char('f'), KeyModifiers::CONTROL) | (KeyCode::Right, _) => {
+                self.move_cursor_right()
+            }
+            (KeyCode::Char('a'), KeyModifiers::CONTROL) | (KeyCode::Home, _) => {
+                self.move_cursor_begin()
+            }
+            (KeyCode::Char('e'), KeyModifiers::CONTROL) | (KeyCode::End, _) => {
+                self.move_cursor_end()
+            }
+            (KeyCode::Enter, KeyModifiers::NONE) => {
+                self.submit();
+                return InputEvent::Submit;
+            }
+            (KeyCode::Backspace, KeyModifiers::NONE) => self.delete_char(),
+            (KeyCode::Esc, KeyModifiers::NONE) => return InputEvent::Cancel,
+            (KeyCode::Char(to_insert), _) => self.enter_char(to_insert),
+            _ => {}
+        }
+        InputEvent::Edit
+    }
+
     pub fn run(&mut self) -> Result<()> {
         let mut term = Term::new()?;
         set_bar_cursor();
@@ -123,19 +180,9 @@ impl Input {
 
             if let Event::Key(key) = event::read()? {
                 if key.kind == KeyEventKind::Press {
-                    match (key.code, key.modifiers) {
-                        (KeyCode::Char('b'), KeyModifiers::CONTROL) => self.move_cursor_left(),
-                        (KeyCode::Char('f'), KeyModifiers::CONTROL) => self.move_cursor_right(),
-                        (KeyCode::Char('a'), KeyModifiers::CONTROL) => self.move_cursor_begin(),
-                        (KeyCode::Char('e'), KeyModifiers::CONTROL) => self.move_cursor_end(),
-                        (KeyCode::Enter, KeyModifiers::NONE) => {
-                            self.submit();
-                            break;
-                        }
-                        (KeyCode::Backspace, KeyModifiers::NONE) => self.delete_char(),
-                        (KeyCode::Esc, KeyModifiers::NONE) => break,
-                        (KeyCode::Char(to_insert), _) => self.enter_char(to_insert),
-                        _ => {}
+                    match self.handle_key(key) {
+                        InputEvent::Submit | InputEvent::Cancel => break,
+                        InputEvent::Edit => {}
                     }
                 }
             }
@@ -181,14 +228,7 @@ impl Input {
         #[allow(clippy::cast_possible_truncation)]
         frame.set_cursor_position(Position::new(
             // Draw the cursor at the current position in the input field.
-            input_area.x
-                + self
-                    .input
-                    .chars()
-                    .take(self.char_idx)
-                    .map(|c| UnicodeWidthChar::width(c).unwrap_or(0))
-                    .sum::<usize>() as u16
-                + 1,
+            input_area.x + self.cursor_width() + 1,
             input_area.y + 1,
         ))
     }
@@ -0,0 +1,417 @@
+use color_eyre::{eyre::eyre, Result};
+use serde_derive::{Deserialize, Serialize};
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::app::{App, Playback, Song};
+use crate::config;
+use crate::library;
+use crate::mode::Repeat;
+
+/// A command sent to the running player, one JSON object a line.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "cmd", rename_all = "snake_case")]
+pub enum Request {
+    /// Resume, or play the selected song when nothing is playing. With a path, play that file or
+    /// directory at once.
+    Play {
+        path: Option<PathBuf>,
+    },
+    Pause,
+    Toggle,
+    Stop,
+    Next,
+    Previous,
+    /// Seek to `position` seconds, or by `offset` seconds.
+    Seek {
+        position: Option<f64>,
+        offset: Option<f64>,
+    },
+    /// Set the volume to `level` percent, or change it by `change` percent.
+    Volume {
+        level: Option<u8>,
+        change: Option<i16>,
+    },
+    Mute,
+    /// Add a file or directory to the end of the queue.
+    Enqueue {
+        path: PathBuf,
+    },
+    Status,
+}
+
+/// The answer to a request, `status` is only set for `Status`.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Response {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<serde_json::Value>,
+}
+
+impl Response {
+    fn ok() -> Self {
+        Self {
+            ok: true,
+            error: None,
+            status: None,
+        }
+    }
+
+    fn error(msg: impl Into<String>) -> Self {
+        Self {
+            ok: false,
+            error: Some(msg.into()),
+            status: None,
+        }
+    }
+}
+
+/// What `Status` reports about the player.
+#[derive(Serialize)]
+struct Status<'a> {
+    state: Playback,
+    song: Option<&'a Song>,
+    position: f64,
+    duration: f64,
+    volume: u8,
+    muted: bool,
+    shuffle: bool,
+    repeat: Repeat,
+    queue: usize,
+}
+
+/// The default socket, `$XDG_RUNTIME_DIR/rmu/rmu.sock`, or `~/.cache/rmu/rmu.sock` without it.
+pub fn socket_path() -> Result<PathBuf> {
+    let mut path = config::xdg_dir("XDG_RUNTIME_DIR", ".cache")?;
+    path.push("rmu.sock");
+    Ok(path)
+}
+
+pub type Pending = (Request, oneshot::Sender<Response>);
+
+/// Songs read in the background for a request naming a path.
+struct Loaded {
+    songs: Result<Vec<Song>>,
+    play: bool,
+    reply: oneshot::Sender<Response>,
+}
+
+/// `Server` listens on the socket, the requests are answered by the main loop through `poll`.
+pub struct Server {
+    path: PathBuf,
+    rx: mpsc::UnboundedReceiver<Pending>,
+    /// Walking a directory would freeze the main loop, the songs come back through here.
+    loaded_tx: mpsc::UnboundedSender<Loaded>,
+    loaded_rx: mpsc::UnboundedReceiver<Loaded>,
+}
+
+impl Server {
+    pub fn bind(path: PathBuf) -> Result<Self> {
+        if path.exists() {
+            // A socket left by a crashed player can be replaced, a live one or any other file can
+            // not.
+            if std::os::unix::net::UnixStream::connect(&path).is_ok() {
+                return Err(eyre!("Another rmu is listening on `{}`", path.display()));
+            }
+            if !std::fs::symlink_metadata(&path)?.file_type().is_socket() {
+                return Err(eyre!("`{}` exists and is not a socket", path.display()));
+            }
+            std::fs::remove_file(&path)?;
+        }
+        // Anyone who can connect controls the player and makes it read any file, the socket is
+        // only for its owner.
+        if let Some(parent) = path.parent() {
+            std::fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(parent)?;
+        }
+        let listener = UnixListener::bind(&path)?;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600))?;
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, tx.clone()));
+            }
+        });
+        let (loaded_tx, loaded_rx) = mpsc::unbounded_channel();
+        Ok(Self {
+            path,
+            rx,
+            loaded_tx,
+            loaded_rx,
+        })
+    }
+
+    /// Answer the requests received since the last call. The songs of `Play` and `Enqueue` are
+    /// read in a background thread, they are answered once read.
+    pub fn poll(&mut self, app: &mut App) {
+        while let Ok((request, reply)) = self.rx.try_recv() {
+            match request {
+                Request::Play { path: Some(path) } => self.load(app, path, true, reply),
+                Request::Enqueue { path } => self.load(app, path, false, reply),
+                request => {
+                    let _ = reply.send(handle(app, request));
+                }
+            }
+        }
+        while let Ok(loaded) = self.loaded_rx.try_recv() {
+            let response = match loaded.songs {
+                Ok(songs) => {
+                    add(app, songs, loaded.play);
+                    Response::ok()
+                }
+                Err(e) => Response::error(e.to_string()),
+            };
+            let _ = loaded.reply.send(response);
+        }
+    }
+
+    fn load(&self, app: &App, path: PathBuf, play: bool, reply: oneshot::Sender<Response>) {
+        let scan = app.config.scan.clone();
+        let tx = self.loaded_tx.clone();
+        tokio::task::spawn_blocking(move || {
+            let songs = library::read_paths(&[path], &scan);
+            let _ = tx.send(Loaded { songs, play, reply });
+        });
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Read the requests of a client line by line and write back the responses.
+async fn serve(stream: UnixStream, tx: mpsc::UnboundedSender<Pending>) -> Result<()> {
+    let (read, mut write) = stream.into_split();
+    let mut lines = BufReader::new(read).lines();
+    while let Some(line) = lines.next_line().await? {
+        let response = match serde_json::from_str(&line) {
+            Ok(request) => {
+                let (reply, response) = oneshot::channel();
+                tx.send((request, reply))?;
+                response.await?
+            }
+            Err(e) => Response::error(format!("Invalid request: {}", e)),
+        };
+        let mut line = serde_json::to_string(&response)?;
+        line.push('\n');
+        write.write_all(line.as_bytes()).await?;
+    }
+    Ok(())
+}
+
+/// Run the request against the player. Requests naming a path are read in the background by
+/// `Server::poll`, they are refused here.
+pub fn handle(app: &mut App, request: Request) -> Response {
+    match run(app, request) {
+        Ok(status) => Response {
+            status,
+            ..Response::ok()
+        },
+        Err(e) => Response::error(e.to_string()),
+    }
+}
+
+fn run(app: &mut App, request: Request) -> Result<Option<serde_json::Value>> {
+    match request {
+        Request::Play { path: Some(_) } | Request::Enqueue { .. } => {
+            return Err(eyre!("Paths are only read through the control socket"));
+        }
+        Request::Play { path: None } => match app.playback() {
+            Playback::Stopped => app.play(),
+            Playback::Paused => app.toggle(),
+            Playback::Playing => {}
+        },
+        Request::Pause => {
+            if app.playback() == Playback::Playing {
+                app.toggle();
+            }
+        }
+        // Media keys have one button for both, it starts the selected song too.
+        Request::Toggle if app.playback() == Playback::Stopped => app.play(),
+        Request::Toggle => app.toggle(),
+        Request::Stop => app.stop(),
+        Request::Next => app.next(),
+        Request::Previous => app.previous(),
+        Request::Seek { position, offset } => {
+            if app.current.is_none() {
+                return Err(eyre!("Nothing is playing"));
+            }
+            match (position, offset) {
+                (Some(position), _) if position.is_finite() => {
+                    let mut position = position.max(0.0);
+                    if app.tot_time > 0.0 {
+                        position = position.min(app.tot_time);
+                    }
+                    let position = Duration::try_from_secs_f64(position)
+                        .map_err(|_| eyre!("Invalid position {}", position))?;
+                    app.seek(position);
+                }
+                (None, Some(offset)) if offset.is_finite() => app.seek_by(offset),
+                (None, None) => return Err(eyre!("Seek needs a position or an offset")),
+                _ => return Err(eyre!("Seek needs a finite position or offset")),
+            }
+        }
+        Request::Volume { level, change } => match (level, change) {
+            (Some(level), _) => app.set_volume(level),
+            (None, Some(change)) => app.change_volume(change),
+            (None, None) => return Err(eyre!("Volume needs a level or a change")),
+        },
+        Request::Mute => app.toggle_mute(),
+        Request::Status => return Ok(Some(serde_json::to_value(status(app))?)),
+    }
+    Ok(None)
+}
+
+/// Play the songs read for a path as if picked in a tab, or add them to the end of the queue.
+fn add(app: &mut App, songs: Vec<Song>, play: bool) {
+    if play {
+        app.play_picked(songs);
+    } else {
+        songs.into_iter().for_each(|song| app.queue.enqueue(song));
+    }
+}
+
+fn status<'a>(app: &'a App) -> Status<'a> {
+    Status {
+        state: app.playback(),
+        song: app.current.as_ref(),
+        position: app.sink.get_pos().as_secs_f64(),
+        duration: app.tot_time,
+        volume: app.volume.level(),
+        muted: app.volume.is_muted(),
+        shuffle: app.shuffle.is_some(),
+        repeat: app.repeat,
+        queue: app.queue.songs.items.len(),
+    }
+}
+
+/// Send a request to the running player and wait for its response.
+pub async fn send(path: &Path, request: &Request) -> Result<Response> {
+    let stream = UnixStream::connect(path).await.map_err(|e| {
+        eyre!(
+            "Can not connect to `{}`, is rmu running? {}",
+            path.display(),
+            e
+        )
+    })?;
+    let (read, mut write) = stream.into_split();
+    let mut line = serde_json::to_string(request)?;
+    line.push('\n');
+    write.write_all(line.as_bytes()).await?;
+    let line = BufReader::new(read)
+        .lines()
+        .next_line()
+        .await?
+        .ok_or_else(|| eyre!("rmu closed the connection"))?;
+    Ok(serde_json::from_str(&line)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[tokio::test]
+    async fn test_ipc() {
+        let dir = testing::temp_dir("ipc");
+        let (a, b) = (dir.join("a.wav"), dir.join("b.wav"));
+        testing::write_wav(&a, 5);
+        testing::write_wav(&b, 5);
+        testing::write_wav(&dir.join("c.wav"), 5);
+        let mut app = testing::fake_app(&dir);
+        // Left by going back, playing a path forgets it.
+        app.future.push(library::read_song(&dir.join("c.wav")));
+        let socket = dir.join("run/rmu.sock");
+        let mut server = Server::bind(socket.clone()).unwrap();
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!((mode(&socket), mode(&dir.join("run"))), (0o600, 0o700));
+        assert!(Server::bind(socket.clone()).is_err());
+
+        let path = socket.clone();
+        let client = tokio::spawn(async move {
+            let request = |request: Request| {
+                let path = path.clone();
+                async move {
+                    let response = send(&path, &request).await.unwrap();
+                    assert!(response.ok, "{:?}", response.error);
+                    response.status.unwrap_or_default()
+                }
+            };
+            assert_eq!(request(Request::Status).await["state"], "stopped");
+
+            request(Request::Play {
+                path: Some(a.clone()),
+            })
+            .await;
+            request(Request::Volume {
+                level: Some(40),
+                change: None,
+            })
+            .await;
+            request(Request::Enqueue { path: b.clone() }).await;
+            request(Request::Seek {
+                position: Some(1.0),
+                offset: None,
+            })
+            .await;
+            let status = request(Request::Status).await;
+            assert_eq!(status["state"], "playing");
+            assert_eq!(status["song"]["path"], a.to_str().unwrap());
+            assert_eq!(status["volume"], 40);
+            assert_eq!(status["queue"], 1);
+            assert!(status["position"].as_f64().unwrap() >= 1.0);
+
+            request(Request::Pause).await;
+            assert_eq!(request(Request::Status).await["state"], "paused");
+            request(Request::Seek {
+                position: None,
+                offset: Some(-1e30),
+            })
+            .await;
+            assert!(request(Request::Status).await["position"].as_f64().unwrap() < 1.0);
+
+            request(Request::Next).await;
+            let status = request(Request::Status).await;
+            assert_eq!(status["song"]["path"], b.to_str().unwrap());
+            assert_eq!(status["queue"], 0);
+
+            let mut stream = UnixStream::connect(&path).await.unwrap();
+            stream.write_all(b"{\"cmd\": \"dance\"}\n").await.unwrap();
+            let mut line = String::new();
+            BufReader::new(stream).read_line(&mut line).await.unwrap();
+            let response: Response = serde_json::from_str(&line).unwrap();
+            assert!(!response.ok);
+        });
+
+        while !client.is_finished() {
+            server.poll(&mut app);
+            app.update(Duration::from_millis(10));
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        client.await.unwrap();
+        let seek = Request::Seek {
+            position: Some(f64::NAN),
+            offset: None,
+        };
+        assert!(!handle(&mut app, seek).ok);
+        let enqueue = Request::Enqueue { path: dir.clone() };
+        assert!(!handle(&mut app, enqueue).ok);
+        drop(server);
+        assert!(!socket.exists());
+        let file = dir.join("notes.txt");
+        std::fs::write(&file, "keep").unwrap();
+        assert!(Server::bind(file.clone()).is_err());
+        assert!(file.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
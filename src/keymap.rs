@@ -0,0 +1,362 @@
+use color_eyre::{eyre::eyre, Result};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// `Action` is what a key sequence does, the keymap maps keys to actions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Quit,
+    Up,
+    Down,
+    Left,
+    Right,
+    Top,
+    Bottom,
+    TabNext,
+    TabPrev,
+    Play,
+    Toggle,
+    Stop,
+    Next,
+    Previous,
+    Replay,
+    SeekForward,
+    SeekBackward,
+    SeekForwardLong,
+    SeekBackwardLong,
+    GoTo,
+    Enqueue,
+    PlayNext,
+    Remove,
+    MoveUp,
+    MoveDown,
+    Clear,
+    Shuffle,
+    Repeat,
+    Sort,
+    SortReverse,
+    Rescan,
+    VolumeUp,
+    VolumeDown,
+    Mute,
+    Search,
+    SearchNext,
+    SearchPrev,
+    ClearSearch,
+    NewPlaylist,
+    RenamePlaylist,
+    AddToPlaylist,
+    ImportPlaylist,
+    ExportPlaylist,
+    SetRoot,
+}
+
+impl FromStr for Action {
+    type Err = color_eyre::Report;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let action = match s {
+            "quit" => Action::Quit,
+            "up" => Action::Up,
+            "down" => Action::Down,
+            "left" => Action::Left,
+            "right" => Action::Right,
+            "top" => Action::Top,
+            "bottom" => Action::Bottom,
+            "tab_next" => Action::TabNext,
+            "tab_prev" => Action::TabPrev,
+            "play" => Action::Play,
+            "toggle" => Action::Toggle,
+            "stop" => Action::Stop,
+            "next" => Action::Next,
+            "previous" => Action::Previous,
+            "replay" => Action::Replay,
+            "seek_forward" => Action::SeekForward,
+            "seek_backward" => Action::SeekBackward,
+            "seek_forward_long" => Action::SeekForwardLong,
+            "seek_backward_long" => Action::SeekBackwardLong,
+            "goto" => Action::GoTo,
+            "enqueue" => Action::Enqueue,
+            "play_next" => Action::PlayNext,
+            "remove" => Action::Remove,
+            "move_up" => Action::MoveUp,
+            "move_down" => Action::MoveDown,
+            "clear" => Action::Clear,
+            "shuffle" => Action::Shuffle,
+            "repeat" => Action::Repeat,
+            "sort" => Action::Sort,
+            "sort_reverse" => Action::SortReverse,
+            "rescan" => Action::Rescan,
+            "volume_up" => Action::VolumeUp,
+            "volume_down" => Action::VolumeDown,
+            "mute" => Action::Mute,
+            "search" => Action::Search,
+            "search_next" => Action::SearchNext,
+            "search_prev" => Action::SearchPrev,
+            "clear_search" => Action::ClearSearch,
+            "new_playlist" => Action::NewPlaylist,
+            "rename_playlist" => Action::RenamePlaylist,
+            "add_to_playlist" => Action::AddToPlaylist,
+            "import_playlist" => Action::ImportPlaylist,
+            "export_playlist" => Action::ExportPlaylist,
+            "set_root" => Action::SetRoot,
+            _ => return Err(eyre!("Unknown action `{}`", s)),
+        };
+        Ok(action)
+    }
+}
+
+/// Default key bindings, the `[keys]` table of the config file is applied on top of them.
+const DEFAULT_KEYS: &[(&str, &str)] = &[
+    ("q", "quit"),
+    ("k", "up"),
+    ("<Up>", "up"),
+    ("j", "down"),
+    ("<Down>", "down"),
+    ("h", "left"),
+    ("l", "right"),
+    ("gg", "top"),
+    ("G", "bottom"),
+    ("<Tab>", "tab_next"),
+    ("<BackTab>", "tab_prev"),
+    ("<Enter>", "play"),
+    ("<Space>", "toggle"),
+    ("x", "stop"),
+    (">", "next"),
+    ("<lt>", "previous"),
+    ("r", "replay"),
+    ("]", "seek_forward"),
+    ("<Right>", "seek_forward"),
+    ("[", "seek_backward"),
+    ("<Left>", "seek_backward"),
+    ("}", "seek_forward_long"),
+    ("{", "seek_backward_long"),
+    ("t", "goto"),
+    ("a", "enqueue"),
+    ("A", "play_next"),
+    ("d", "remove"),
+    ("<Del>", "remove"),
+    ("K", "move_up"),
+    ("J", "move_down"),
+    ("e", "clear"),
+    ("s", "shuffle"),
+    ("R", "repeat"),
+    ("o", "sort"),
+    ("O", "sort_reverse"),
+    ("u", "rescan"),
+    ("+", "volume_up"),
+    ("-", "volume_down"),
+    ("m", "mute"),
+    ("/", "search"),
+    ("n", "search_next"),
+    ("N", "search_prev"),
+    ("<Esc>", "clear_search"),
+    ("c", "new_playlist"),
+    ("C", "rename_playlist"),
+    ("p", "add_to_playlist"),
+    ("i", "import_playlist"),
+    ("w", "export_playlist"),
+    ("S", "set_root"),
+];
+
+/// A single key press with its modifiers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl Key {
+    /// Shift is already part of the char itself(`G` instead of `g`), so it is dropped to match
+    /// the same key no matter how the terminal reports it.
+    fn new(code: KeyCode, mut modifiers: KeyModifiers) -> Self {
+        if let KeyCode::Char(_) = code {
+            modifiers.remove(KeyModifiers::SHIFT);
+        }
+        Self { code, modifiers }
+    }
+}
+
+impl From<KeyEvent> for Key {
+    fn from(key: KeyEvent) -> Self {
+        Key::new(key.code, key.modifiers)
+    }
+}
+
+/// Parse a key sequence like `gg`, `<C-n>` or `<A-Enter>`. Use `<lt>` for a literal `<`.
+fn parse_keys(s: &str) -> Result<Vec<Key>> {
+    let mut keys = Vec::new();
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '<' {
+            keys.push(Key::new(KeyCode::Char(c), KeyModifiers::NONE));
+            continue;
+        }
+
+        let name: String = chars.by_ref().take_while(|&c| c != '>').collect();
+        let mut modifiers = KeyModifiers::NONE;
+        let mut parts: Vec<&str> = name.split('-').collect();
+        // Handle `<C-->`, the last part is empty if the key itself is a dash.
+        if parts.len() > 1 && parts[parts.len() - 1].is_empty() {
+            parts.pop();
+            let last = parts.len() - 1;
+            parts[last] = "-";
+        }
+        let (code, mods) = parts
+            .split_last()
+            .ok_or_else(|| eyre!("Empty key in `{}`", s))?;
+        for m in mods {
+            modifiers |= match m.to_ascii_lowercase().as_str() {
+                "c" => KeyModifiers::CONTROL,
+                "a" | "m" => KeyModifiers::ALT,
+                "s" => KeyModifiers::SHIFT,
+                _ => return Err(eyre!("Unknown modifier `{}` in `{}`", m, s)),
+            };
+        }
+        let code = match code.to_ascii_lowercase().as_str() {
+            "lt" => KeyCode::Char('<'),
+            "space" => KeyCode::Char(' '),
+            "enter" | "cr" => KeyCode::Enter,
+            "esc" => KeyCode::Esc,
+            "tab" => KeyCode::Tab,
+            "backtab" => KeyCode::BackTab,
+            "bs" | "backspace" => KeyCode::Backspace,
+            "del" | "delete" => KeyCode::Delete,
+            "up" => KeyCode::Up,
+            "down" => KeyCode::Down,
+            "left" => KeyCode::Left,
+            "right" => KeyCode::Right,
+            "home" => KeyCode::Home,
+            "end" => KeyCode::End,
+            "pageup" => KeyCode::PageUp,
+            "pagedown" => KeyCode::PageDown,
+            f if f.len() > 1 && f.starts_with('f') => KeyCode::F(
+                f[1..]
+                    .parse()
+                    .map_err(|_| eyre!("Unknown key `{}` in `{}`", code, s))?,
+            ),
+            _ => {
+                let mut c = code.chars();
+                match (c.next(), c.next()) {
+                    (Some(c), None) => KeyCode::Char(c),
+                    _ => return Err(eyre!("Unknown key `{}` in `{}`", code, s)),
+                }
+            }
+        };
+        keys.push(Key::new(code, modifiers));
+    }
+    Ok(keys)
+}
+
+/// `Keymap` turns the key presses into actions, keeping track of unfinished sequences.
+pub struct Keymap {
+    bindings: HashMap<Vec<Key>, Action>,
+    /// Keys pressed so far of an unfinished sequence.
+    pending: Vec<Key>,
+}
+
+impl Keymap {
+    /// Create the keymap from the defaults and the user bindings, bind to `none` to remove a key.
+    pub fn new(keys: &HashMap<String, String>) -> Result<Self> {
+        let mut bindings = HashMap::new();
+        for (seq, action) in DEFAULT_KEYS {
+            bindings.insert(parse_keys(seq)?, action.parse()?);
+        }
+        for (seq, action) in keys {
+            let seq = parse_keys(seq)?;
+            if action == "none" {
+                bindings.remove(&seq);
+            } else {
+                bindings.insert(seq, action.parse()?);
+            }
+        }
+        Ok(Self {
+            bindings,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Whether some binding is longer than and starts with the pending keys.
+    fn is_prefix(&self) -> bool {
+        self.bindings
+            .keys()
+            .any(|seq| seq.len() > self.pending.len() && seq.starts_with(&self.pending))
+    }
+
+    /// Feed a key press, return the action once a whole sequence has been typed.
+    pub fn feed(&mut self, key: impl Into<Key>) -> Option<Action> {
+        self.pending.push(key.into());
+        if let Some(&action) = self.bindings.get(&self.pending) {
+            self.pending.clear();
+            return Some(action);
+        }
+        if self.is_prefix() {
+            return None;
+        }
+
+        // The sequence is broken, start over from the last key.
+        let last = self.pending.pop();
+        let retry = !self.pending.is_empty();
+        self.pending.clear();
+        match last {
+            Some(key) if retry => self.feed(key),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(c: char) -> Key {
+        Key::new(KeyCode::Char(c), KeyModifiers::NONE)
+    }
+
+    #[test]
+    fn test_parse_keys() {
+        assert_eq!(parse_keys("gg").unwrap(), vec![key('g'), key('g')]);
+        assert_eq!(
+            parse_keys("<C-n><lt>").unwrap(),
+            vec![
+                Key::new(KeyCode::Char('n'), KeyModifiers::CONTROL),
+                key('<')
+            ]
+        );
+        assert_eq!(
+            parse_keys("<A-Enter>").unwrap(),
+            vec![Key::new(KeyCode::Enter, KeyModifiers::ALT)]
+        );
+        assert_eq!(
+            parse_keys("<C-->").unwrap(),
+            vec![Key::new(KeyCode::Char('-'), KeyModifiers::CONTROL)]
+        );
+        assert!(parse_keys("<Hyper-x>").is_err());
+    }
+
+    #[test]
+    fn test_feed() {
+        let keys = HashMap::from([
+            (String::from("<C-j>"), String::from("down")),
+            (String::from("j"), String::from("none")),
+        ]);
+        let mut keymap = Keymap::new(&keys).unwrap();
+
+        assert_eq!(keymap.feed(key('g')), None);
+        assert_eq!(keymap.feed(key('g')), Some(Action::Top));
+        // A broken sequence starts over from the last key.
+        assert_eq!(keymap.feed(key('g')), None);
+        assert_eq!(keymap.feed(key('q')), Some(Action::Quit));
+        // Shift is part of the char.
+        assert_eq!(
+            keymap.feed(Key::new(KeyCode::Char('G'), KeyModifiers::SHIFT)),
+            Some(Action::Bottom)
+        );
+        assert_eq!(keymap.feed(key('j')), None);
+        assert_eq!(
+            keymap.feed(Key::new(KeyCode::Char('j'), KeyModifiers::CONTROL)),
+            Some(Action::Down)
+        );
+    }
+}
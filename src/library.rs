@@ -0,0 +1,518 @@
+use color_eyre::Result;
+use rodio::{Decoder, Source};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, UNIX_EPOCH};
+use tokio::sync::{mpsc, oneshot};
+use walkdir::{DirEntry, WalkDir};
+
+use crate::app::Song;
+use crate::config::{self, Config, Scan};
+use crate::error::Error;
+use crate::format;
+use crate::meta::Tags;
+
+/// Progress of a library scan.
+pub enum Event {
+    /// Number of files found in the music directory, sent before any song.
+    Total(usize),
+    /// A song has been read.
+    Song(Box<Song>),
+    /// The scan is finished.
+    Done(Result<()>),
+}
+
+/// Bump it whenever the cached fields change, an outdated cache is ignored.
+const CACHE_VERSION: u32 = 4;
+
+/// A cached song, valid as long as the file keeps the same modification time and size.
+#[derive(Serialize, Deserialize, Debug)]
+struct Entry {
+    path: String,
+    mtime: u64,
+    size: u64,
+    time: f64,
+    tags: Tags,
+    /// The file can not be decoded, it is kept in the list but never played.
+    #[serde(default)]
+    unplayable: bool,
+    /// The modification time when the file was first scanned, kept when it changes.
+    added: u64,
+}
+
+impl Entry {
+    /// Read the duration and tags of the file, a file which can not be decoded is unplayable.
+    fn read(path: &Path, mtime: u64, size: u64) -> Self {
+        let source = decode(path);
+        let time = source
+            .as_ref()
+            .ok()
+            .and_then(|source| source.total_duration())
+            .unwrap_or(Duration::ZERO);
+        Self {
+            path: path.to_string_lossy().to_string(),
+            mtime,
+            size,
+            time: time.as_secs_f64(),
+            tags: Tags::read(path),
+            unplayable: source.is_err(),
+            added: mtime,
+        }
+    }
+
+    fn song(&self, root: &Path) -> Song {
+        let path = Path::new(&self.path);
+        Song {
+            name: self.path.clone(),
+            rel_path: path.strip_prefix(root).unwrap_or(path).to_path_buf(),
+            time: self.time,
+            tags: self.tags.clone(),
+            unplayable: self.unplayable,
+            added: self.added,
+        }
+    }
+}
+
+/// `Cache` is the on-disk library database, so unchanged files are not decoded on every launch.
+#[derive(Serialize, Deserialize, Debug)]
+struct Cache {
+    version: u32,
+    #[serde(default)]
+    songs: Vec<Entry>,
+}
+
+impl Cache {
+    fn new(songs: Vec<Entry>) -> Self {
+        Self {
+            version: CACHE_VERSION,
+            songs,
+        }
+    }
+
+    /// Load the cached songs keyed by path, a missing or outdated cache is empty.
+    fn load(path: &Path) -> HashMap<String, Entry> {
+        fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| toml::from_str::<Cache>(&contents).ok())
+            .filter(|cache| cache.version == CACHE_VERSION)
+            .map(|cache| {
+                cache
+                    .songs
+                    .into_iter()
+                    .map(|entry| (entry.path.clone(), entry))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Write the cache to a temporary file first, so it is never left half written.
+    fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("toml.tmp");
+        fs::write(&tmp, toml::to_string(self)?)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+}
+
+/// The cache file, `$XDG_CACHE_HOME/rmu/library.toml`.
+pub fn cache_path() -> Result<PathBuf> {
+    let mut path = config::xdg_dir("XDG_CACHE_HOME", ".cache")?;
+    path.push("library.toml");
+    Ok(path)
+}
+
+/// Open the file and its decoder.
+pub fn decode(path: &Path) -> Result<Decoder<BufReader<File>>, Error> {
+    let file = File::open(path).map_err(|source| Error::Open {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    Decoder::new(BufReader::new(file)).map_err(|source| Error::Decode {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+/// Whether the entry is a hidden file or directory.
+fn is_hidden(entry: &DirEntry) -> bool {
+    entry
+        .file_name()
+        .to_str()
+        .map(|name| name.starts_with('.'))
+        .unwrap_or(false)
+}
+
+/// Whether the file should be added to the library, by its extension or else by its header.
+pub fn is_audio(path: &Path, scan: &Scan) -> bool {
+    let ext = path
+        .extension()
+        .map(|ext| ext.to_string_lossy())
+        .unwrap_or_default();
+    let matches = |exts: &[String]| {
+        exts.iter()
+            .any(|e| e.trim_start_matches('.').eq_ignore_ascii_case(&ext))
+    };
+    if matches(&scan.exclude) {
+        return false;
+    }
+    matches(&scan.extensions) || format::sniff(path).is_some()
+}
+
+/// Walk the music directory recursively and collect all audio files.
+fn walk(root: &Path, scan: &Scan) -> Vec<PathBuf> {
+    let mut walker = WalkDir::new(root).follow_links(scan.follow_links);
+    if let Some(depth) = scan.max_depth {
+        walker = walker.max_depth(depth);
+    }
+
+    walker
+        .into_iter()
+        // Never filter the root itself, even if it is a hidden directory.
+        .filter_entry(|entry| entry.depth() == 0 || !scan.skip_hidden || !is_hidden(entry))
+        // Unreadable entries and symlink loops are skipped.
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.into_path())
+        .filter(|path| is_audio(path, scan))
+        .collect()
+}
+
+/// The modification time of the file in seconds since the epoch, `0` if it is unknown.
+fn modified(metadata: &fs::Metadata) -> u64 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|time| time.as_secs())
+        .unwrap_or(0)
+}
+
+/// Scan the music directory, only the files changed since the last scan in `cache_path` are
+/// decoded. `report` receives the number of files found first, then every song as soon as it is
+/// read.
+pub fn scan(config: &Config, cache_path: &Path, mut report: impl FnMut(Event)) -> Result<()> {
+    let root = Path::new(&config.path);
+    // A missing music directory is an error, not an empty library.
+    fs::read_dir(root).map_err(|source| Error::MusicDir {
+        path: root.to_path_buf(),
+        source,
+    })?;
+    let mut cache = Cache::load(cache_path);
+
+    let paths = walk(root, &config.scan);
+    report(Event::Total(paths.len()));
+
+    let mut entries = Vec::new();
+    for path in paths {
+        let Ok(metadata) = fs::metadata(&path) else {
+            continue;
+        };
+        let mtime = modified(&metadata);
+        let size = metadata.len();
+
+        let entry = match cache.remove(path.to_string_lossy().as_ref()) {
+            Some(entry) if entry.mtime == mtime && entry.size == size => entry,
+            Some(entry) => Entry {
+                added: entry.added,
+                ..Entry::read(&path, mtime, size)
+            },
+            None => Entry::read(&path, mtime, size),
+        };
+        report(Event::Song(Box::new(entry.song(root))));
+        entries.push(entry);
+    }
+
+    // The cache is only a speedup, failing to write it must not stop the player.
+    let _ = Cache::new(entries).save(cache_path);
+    Ok(())
+}
+
+/// Read the files given on the command line, directories are walked and sorted by path. Files
+/// are read as they are, whatever their extension.
+pub fn read_paths(paths: &[PathBuf], scan: &Scan) -> Result<Vec<Song>> {
+    let mut songs = Vec::new();
+    for path in paths {
+        let path = std::path::absolute(path)?;
+        let root = path.parent().unwrap_or(&path).to_path_buf();
+        let mut files = if path.is_dir() {
+            walk(&path, scan)
+        } else if path.is_file() {
+            vec![path.clone()]
+        } else {
+            return Err(Error::Open {
+                path,
+                source: std::io::ErrorKind::NotFound.into(),
+            }
+            .into());
+        };
+        files.sort();
+        songs.extend(files.iter().map(|file| Entry::read(file, 0, 0).song(&root)));
+    }
+    Ok(songs)
+}
+
+/// Read the audio files under the paths changed in the music directory `root`, by the library
+/// watcher. Paths which are gone have no songs, paths skipped by the scan are ignored. The cache at
+/// `cache_path` is updated, so the next scan does not read them again.
+pub fn read_changed(
+    root: &Path,
+    paths: &[PathBuf],
+    scan: &Scan,
+    cache_path: Option<&Path>,
+) -> Vec<Song> {
+    let mut cache = cache_path.map(Cache::load).unwrap_or_default();
+    let mut entries = Vec::new();
+    for path in paths {
+        let Ok(rel) = path.strip_prefix(root) else {
+            continue;
+        };
+        let hidden = rel
+            .components()
+            .any(|part| part.as_os_str().to_string_lossy().starts_with('.'));
+        let depth = rel.components().count();
+        if (scan.skip_hidden && hidden) || scan.max_depth.is_some_and(|max| depth > max) {
+            continue;
+        }
+        // The depth is counted from the music directory.
+        let scan = Scan {
+            max_depth: scan.max_depth.map(|max| max - depth),
+            ..scan.clone()
+        };
+        for file in walk(path, &scan) {
+            let Ok(metadata) = fs::metadata(&file) else {
+                continue;
+            };
+            let (mtime, size) = (modified(&metadata), metadata.len());
+            entries.push(match cache.remove(file.to_string_lossy().as_ref()) {
+                Some(entry) if entry.mtime == mtime && entry.size == size => entry,
+                Some(entry) => Entry {
+                    added: entry.added,
+                    ..Entry::read(&file, mtime, size)
+                },
+                None => Entry::read(&file, mtime, size),
+            });
+        }
+    }
+    let songs = entries.iter().map(|entry| entry.song(root)).collect();
+
+    if let Some(cache_path) = cache_path {
+        cache.retain(|name, _| !paths.iter().any(|path| Path::new(name).starts_with(path)));
+        let mut cached: Vec<Entry> = cache.into_values().collect();
+        cached.extend(entries);
+        // The cache is only a speedup, like after a scan.
+        let _ = Cache::new(cached).save(cache_path);
+    }
+    songs
+}
+
+/// Read the changed paths of the music directory in a background thread, the songs are sent
+/// through the channel once all are read.
+pub fn spawn_changed(config: Config, paths: Vec<PathBuf>) -> oneshot::Receiver<Vec<Song>> {
+    let (tx, rx) = oneshot::channel();
+    tokio::task::spawn_blocking(move || {
+        let cache_path = config.cache.clone().map_or_else(cache_path, Ok).ok();
+        let songs = read_changed(
+            Path::new(&config.path),
+            &paths,
+            &config.scan,
+            cache_path.as_deref(),
+        );
+        let _ = tx.send(songs);
+    });
+    rx
+}
+
+/// Read a single song, a file which can not be decoded is unplayable.
+pub fn read_song(path: &Path) -> Song {
+    Entry::read(path, 0, 0).song(path.parent().unwrap_or(path))
+}
+
+/// Read the songs in a background thread, files which are gone are skipped. The songs are sent
+/// through the channel once all are read.
+pub fn spawn_read(paths: Vec<PathBuf>) -> oneshot::Receiver<Vec<Song>> {
+    let (tx, rx) = oneshot::channel();
+    tokio::task::spawn_blocking(move || {
+        let songs = paths
+            .iter()
+            .filter(|path| path.exists())
+            .map(|path| read_song(path))
+            .collect();
+        let _ = tx.send(songs);
+    });
+    rx
+}
+
+/// Scan the library in a background thread, the progress is streamed through the channel.
+pub fn spawn_scan(config: Config) -> mpsc::UnboundedReceiver<Event> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    tokio::task::spawn_blocking(move || {
+        let result = config
+            .cache
+            .clone()
+            .map_or_else(cache_path, Ok)
+            .and_then(|cache_path| {
+                scan(&config, &cache_path, |event| {
+                    let _ = tx.send(event);
+                })
+            });
+        let _ = tx.send(Event::Done(result));
+    });
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn test_scan() {
+        let dir = testing::temp_dir("scan");
+        let root = dir.join("music");
+        fs::create_dir_all(root.join("Album")).unwrap();
+        testing::write_wav(&root.join("top.wav"), 1);
+        testing::write_wav(&root.join("Album/01.wav"), 2);
+        fs::write(root.join("Album/cover.jpg"), b"").unwrap();
+        let config = Config {
+            path: root.to_string_lossy().to_string(),
+            ..Config::default()
+        };
+        let cache_path = dir.join("library.toml");
+
+        let scan_songs = || {
+            let mut total = None;
+            let mut songs = Vec::new();
+            scan(&config, &cache_path, |event| match event {
+                Event::Total(n) => total = Some(n),
+                Event::Song(song) => songs.push(*song),
+                Event::Done(_) => {}
+            })
+            .unwrap();
+            songs.sort_by(|a, b| a.name.cmp(&b.name));
+            (total, songs)
+        };
+        let (total, songs) = scan_songs();
+        assert_eq!(total, Some(2));
+        let found: Vec<_> = songs
+            .iter()
+            .map(|song| (song.rel_path.clone(), song.time))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (PathBuf::from("Album/01.wav"), 2.0),
+                (PathBuf::from("top.wav"), 1.0)
+            ]
+        );
+        assert_eq!(Cache::load(&cache_path).len(), 2);
+
+        // Read from the cache the second time, a removed file is dropped from it.
+        fs::remove_file(root.join("top.wav")).unwrap();
+        let (_, songs) = scan_songs();
+        assert_eq!(songs.len(), 1);
+        assert_eq!(Cache::load(&cache_path).len(), 1);
+
+        // Files read by the watcher are cached too, removed ones are dropped.
+        fs::create_dir_all(root.join("New")).unwrap();
+        testing::write_wav(&root.join("New/02.wav"), 1);
+        let changed = [root.join("New")];
+        let songs = read_changed(&root, &changed, &config.scan, Some(&cache_path));
+        assert_eq!(songs[0].rel_path, PathBuf::from("New/02.wav"));
+        assert!(Cache::load(&cache_path).contains_key(root.join("New/02.wav").to_str().unwrap()));
+        fs::remove_dir_all(root.join("Album")).unwrap();
+        let changed = [root.join("Album")];
+        assert!(read_changed(&root, &changed, &config.scan, Some(&cache_path)).is_empty());
+        assert_eq!(Cache::load(&cache_path).len(), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cache() {
+        let path = std::env::temp_dir().join(format!("rmu-cache-{}.toml", std::process::id()));
+        let entry = Entry {
+            path: String::from("/music/Artist/Album/01 Track.mp3"),
+            mtime: 1700000000,
+            size: 4096,
+            time: 215.5,
+            tags: Tags {
+                title: Some(String::from("Track")),
+                track: Some(1),
+                ..Tags::default()
+            },
+            unplayable: true,
+            added: 1600000000,
+        };
+        Cache::new(vec![entry]).save(&path).unwrap();
+
+        let mut cache = Cache::load(&path);
+        let entry = cache.remove("/music/Artist/Album/01 Track.mp3").unwrap();
+        assert_eq!(
+            (entry.mtime, entry.size, entry.time, entry.added),
+            (1700000000, 4096, 215.5, 1600000000)
+        );
+        assert_eq!(entry.tags.title.as_deref(), Some("Track"));
+        assert_eq!(entry.tags.track, Some(1));
+        assert!(entry.unplayable);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_walk() {
+        let root = std::env::temp_dir().join(format!("rmu-walk-{}", std::process::id()));
+        let album = root.join("Artist").join("Album");
+        std::fs::create_dir_all(&album).unwrap();
+        std::fs::create_dir_all(root.join(".hidden")).unwrap();
+        for file in [
+            root.join("top.mp3"),
+            album.join("01 Track.MP3"),
+            album.join("cover.jpg"),
+            album.join("notes.txt"),
+            root.join(".hidden").join("secret.mp3"),
+        ] {
+            std::fs::write(file, b"").unwrap();
+        }
+        // No extension, found by its header.
+        std::fs::write(album.join("02 Track"), b"fLaC\x00\x00\x00\x22").unwrap();
+
+        let mut found = walk(&root, &Scan::default());
+        found.sort();
+        assert_eq!(
+            found,
+            vec![
+                album.join("01 Track.MP3"),
+                album.join("02 Track"),
+                root.join("top.mp3")
+            ]
+        );
+
+        let exclude = Scan {
+            exclude: vec![String::from("mp3")],
+            ..Scan::default()
+        };
+        assert_eq!(walk(&root, &exclude), vec![album.join("02 Track")]);
+
+        let shallow = Scan {
+            max_depth: Some(1),
+            ..Scan::default()
+        };
+        assert_eq!(walk(&root, &shallow), vec![root.join("top.mp3")]);
+
+        let all = Scan {
+            skip_hidden: false,
+            ..Scan::default()
+        };
+        assert_eq!(walk(&root, &all).len(), 4);
+        // A broken file is kept, but can not be played.
+        assert!(Entry::read(&album.join("02 Track"), 0, 0).unplayable);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -1,15 +1,45 @@
 mod app;
+mod browser;
+mod cli;
 mod config;
+mod error;
+mod fade;
+mod files;
+mod format;
 mod input;
+mod ipc;
+mod keymap;
+mod library;
+mod meta;
+mod mode;
+mod mpris;
+mod output;
+mod playlist;
+mod queue;
+mod replaygain;
+mod search;
+mod sort;
+mod state;
 mod term;
+#[cfg(test)]
+mod testing;
 mod ui;
+mod visualizer;
+mod volume;
+mod watch;
 
+use clap::Parser;
+use cli::Cli;
 use color_eyre::Result;
 
 /// Main logical with tokio.
 async fn tokio_main() -> Result<()> {
     color_eyre::install()?;
-    ui::run().await?;
+    let cli = Cli::parse();
+    match &cli.command {
+        Some(command) => cli.run(command).await?,
+        None => ui::run(&cli).await?,
+    }
     Ok(())
 }
 
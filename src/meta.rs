@@ -0,0 +1,224 @@
+use serde_derive::{Deserialize, Serialize};
+use std::fs::File;
+use std::path::Path;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::{MetadataOptions, MetadataRevision, StandardTagKey, Value};
+use symphonia::core::probe::Hint;
+
+/// `Tags` holds the metadata read from ID3v2, Vorbis comments, FLAC or MP4 atoms.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Tags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub track: Option<u32>,
+    pub disc: Option<u32>,
+    pub year: Option<i32>,
+    pub genre: Option<String>,
+    pub comment: Option<String>,
+    /// ReplayGain adjustments in dB, and the peak amplitudes where `1.0` is full scale.
+    pub track_gain: Option<f32>,
+    pub track_peak: Option<f32>,
+    pub album_gain: Option<f32>,
+    pub album_peak: Option<f32>,
+}
+
+impl Tags {
+    /// Read the tags of the file, missing or unreadable tags are left empty.
+    pub fn read(path: &Path) -> Self {
+        let mut tags = Tags::default();
+        let Ok(file) = File::open(path) else {
+            return tags;
+        };
+
+        let mut hint = Hint::new();
+        if let Some(ext) = path.extension().and_then(|ext| ext.to_str()) {
+            hint.with_extension(ext);
+        }
+        let mss = MediaSourceStream::new(Box::new(file), Default::default());
+        let Ok(mut probed) = symphonia::default::get_probe().format(
+            &hint,
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        ) else {
+            return tags;
+        };
+
+        // Tags inside the container take precedence over the ones found before it(e.g. ID3v2).
+        if let Some(rev) = probed.format.metadata().current() {
+            tags.merge(rev);
+        }
+        if let Some(rev) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+            tags.merge(rev);
+        }
+        tags
+    }
+
+    /// Fill the fields still empty with the tags of this revision.
+    fn merge(&mut self, rev: &MetadataRevision) {
+        for tag in rev.tags() {
+            let Some(key) = tag.std_key.or_else(|| replaygain_key(&tag.key)) else {
+                continue;
+            };
+            let value = match &tag.value {
+                // Some writers pad strings with NUL bytes.
+                Value::String(s) => s
+                    .trim_matches(|c: char| c == '\0' || c.is_whitespace())
+                    .to_string(),
+                Value::UnsignedInt(n) => n.to_string(),
+                Value::SignedInt(n) => n.to_string(),
+                Value::Float(n) => n.to_string(),
+                _ => continue,
+            };
+            if value.is_empty() {
+                continue;
+            }
+
+            match key {
+                StandardTagKey::TrackTitle => fill(&mut self.title, value),
+                StandardTagKey::Artist => fill(&mut self.artist, value),
+                StandardTagKey::Album => fill(&mut self.album, value),
+                StandardTagKey::AlbumArtist => fill(&mut self.album_artist, value),
+                StandardTagKey::TrackNumber => fill_opt(&mut self.track, leading_number(&value)),
+                StandardTagKey::DiscNumber => fill_opt(&mut self.disc, leading_number(&value)),
+                StandardTagKey::Date | StandardTagKey::ReleaseDate => {
+                    fill_opt(&mut self.year, leading_number(&value))
+                }
+                StandardTagKey::Genre => fill(&mut self.genre, value),
+                StandardTagKey::Comment => fill(&mut self.comment, value),
+                StandardTagKey::ReplayGainTrackGain => {
+                    fill_opt(&mut self.track_gain, number(&value))
+                }
+                StandardTagKey::ReplayGainTrackPeak => {
+                    fill_opt(&mut self.track_peak, number(&value))
+                }
+                StandardTagKey::ReplayGainAlbumGain => {
+                    fill_opt(&mut self.album_gain, number(&value))
+                }
+                StandardTagKey::ReplayGainAlbumPeak => {
+                    fill_opt(&mut self.album_peak, number(&value))
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+fn fill(field: &mut Option<String>, value: String) {
+    if field.is_none() {
+        *field = Some(value);
+    }
+}
+
+fn fill_opt<T>(field: &mut Option<T>, value: Option<T>) {
+    if field.is_none() {
+        *field = value;
+    }
+}
+
+/// Map the ReplayGain tags the readers leave without a standard key, like lowercase ID3v2 `TXXX`
+/// frames or MP4 `----:com.apple.iTunes:replaygain_track_gain` atoms.
+fn replaygain_key(key: &str) -> Option<StandardTagKey> {
+    let name = key.rsplit(':').next()?.to_ascii_lowercase();
+    match name.as_str() {
+        "replaygain_track_gain" => Some(StandardTagKey::ReplayGainTrackGain),
+        "replaygain_track_peak" => Some(StandardTagKey::ReplayGainTrackPeak),
+        "replaygain_album_gain" => Some(StandardTagKey::ReplayGainAlbumGain),
+        "replaygain_album_peak" => Some(StandardTagKey::ReplayGainAlbumPeak),
+        _ => None,
+    }
+}
+
+/// Parse a decimal number followed by a unit, like `-6.54 dB`.
+fn number(value: &str) -> Option<f32> {
+    value
+        .trim_end_matches(|c: char| c.is_ascii_alphabetic())
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Parse the number at the beginning, handles values like `3/12` or `2001-05-02`.
+fn leading_number<T: std::str::FromStr>(value: &str) -> Option<T> {
+    let end = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    value[..end].parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use symphonia::core::meta::{MetadataBuilder, Tag};
+
+    fn revision(tags: &[(Option<StandardTagKey>, &str, Value)]) -> MetadataRevision {
+        let mut builder = MetadataBuilder::new();
+        for (std_key, key, value) in tags {
+            builder.add_tag(Tag::new(*std_key, key, value.clone()));
+        }
+        builder.metadata()
+    }
+
+    #[test]
+    fn test_leading_number() {
+        assert_eq!(leading_number::<u32>("3/12"), Some(3));
+        assert_eq!(leading_number::<i32>("2001-05-02"), Some(2001));
+        assert_eq!(leading_number::<u32>("07"), Some(7));
+        assert_eq!(leading_number::<u32>(""), None);
+        assert_eq!(leading_number::<u32>("A1"), None);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut tags = Tags::default();
+        tags.merge(&revision(&[
+            (
+                Some(StandardTagKey::Artist),
+                "ARTIST",
+                Value::from("First\0"),
+            ),
+            (Some(StandardTagKey::Album), "ALBUM", Value::from("  ")),
+            (
+                Some(StandardTagKey::TrackNumber),
+                "TRACK",
+                Value::from("3/12"),
+            ),
+            (
+                Some(StandardTagKey::Date),
+                "DATE",
+                Value::from("2001-05-02"),
+            ),
+            (None, "REPLAYGAIN_TRACK_GAIN", Value::from("-6.54 dB")),
+        ]));
+        // The first revision wins, empty values are skipped.
+        tags.merge(&revision(&[
+            (
+                Some(StandardTagKey::Artist),
+                "ARTIST",
+                Value::from("Second"),
+            ),
+            (Some(StandardTagKey::Album), "ALBUM", Value::from("Album")),
+            (
+                Some(StandardTagKey::TrackNumber),
+                "TRACK",
+                Value::UnsignedInt(9),
+            ),
+            (Some(StandardTagKey::DiscNumber), "DISC", Value::from("")),
+        ]));
+        assert_eq!(
+            tags,
+            Tags {
+                artist: Some("First".to_string()),
+                album: Some("Album".to_string()),
+                track: Some(3),
+                year: Some(2001),
+                track_gain: Some(-6.54),
+                ..Tags::default()
+            }
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use serde_derive::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::app::Song;
+
+/// What to play once the current song or the whole library is over.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Repeat {
+    /// Stop at the end of the library.
+    #[default]
+    Off,
+    /// Play the current song again and again.
+    One,
+    /// Start over from the beginning of the library.
+    All,
+}
+
+impl Repeat {
+    /// Switch to the next mode, off -> all -> one -> off.
+    pub fn cycle(self) -> Self {
+        match self {
+            Repeat::Off => Repeat::All,
+            Repeat::All => Repeat::One,
+            Repeat::One => Repeat::Off,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Repeat::Off => "",
+            Repeat::One => "repeat one",
+            Repeat::All => "repeat all",
+        }
+    }
+}
+
+/// `Shuffle` deals the songs of library in a random order, every song is played once before any
+/// of them is played again.
+pub struct Shuffle {
+    /// State of the splitmix64 generator, the same seed always gives the same order.
+    state: u64,
+    /// Names of the songs not played yet, the next one is at the end.
+    bag: Vec<String>,
+    /// Whether the songs have been dealt once.
+    dealt: bool,
+}
+
+impl Shuffle {
+    /// Create the shuffle, a random seed is used if none is given.
+    pub fn new(seed: Option<u64>) -> Self {
+        let seed = seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_nanos() as u64)
+                .unwrap_or(0)
+        });
+        Self {
+            state: seed,
+            bag: Vec::new(),
+            dealt: false,
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Fill the bag with all songs but the playing one in a random order.
+    fn deal(&mut self, songs: &[Song], current: Option<&Song>) {
+        self.bag = songs
+            .iter()
+            .filter(|song| current.is_none_or(|current| current.name != song.name))
+            .map(|song| song.name.clone())
+            .collect();
+        // Fisher-Yates shuffle.
+        for i in (1..self.bag.len()).rev() {
+            let j = (self.next_u64() % (i as u64 + 1)) as usize;
+            self.bag.swap(i, j);
+        }
+    }
+
+    /// Take the next song. Once all songs are played, they are dealt again if `repeat` is set.
+    pub fn next(&mut self, songs: &[Song], current: Option<&Song>, repeat: bool) -> Option<Song> {
+        for _ in 0..2 {
+            // Songs removed from library since the deal are skipped.
+            while let Some(name) = self.bag.pop() {
+                if let Some(song) = songs.iter().find(|song| song.name == name) {
+                    return Some(song.clone());
+                }
+            }
+            if self.dealt && !repeat {
+                return None;
+            }
+            self.deal(songs, current);
+            self.dealt = true;
+            // A library of a single song only has the playing one.
+            if self.bag.is_empty() {
+                return current.filter(|_| repeat).cloned();
+            }
+        }
+        None
+    }
+
+    /// Put the song back, it is the next one taken.
+    pub fn put_back(&mut self, name: String) {
+        self.bag.push(name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta::Tags;
+    use std::path::PathBuf;
+
+    fn songs(n: usize) -> Vec<Song> {
+        (0..n)
+            .map(|i| Song {
+                name: i.to_string(),
+                rel_path: PathBuf::from(i.to_string()),
+                time: 0.0,
+                tags: Tags::default(),
+                unplayable: false,
+                added: 0,
+            })
+            .collect()
+    }
+
+    fn deal_all(shuffle: &mut Shuffle, songs: &[Song]) -> Vec<String> {
+        std::iter::from_fn(|| shuffle.next(songs, None, false))
+            .map(|song| song.name)
+            .collect()
+    }
+
+    #[test]
+    fn test_shuffle() {
+        let songs = songs(20);
+        let first = deal_all(&mut Shuffle::new(Some(42)), &songs);
+        let second = deal_all(&mut Shuffle::new(Some(42)), &songs);
+        assert_eq!(first, second);
+
+        // Every song exactly once.
+        let mut sorted: Vec<usize> = first.iter().map(|name| name.parse().unwrap()).collect();
+        sorted.sort();
+        assert_eq!(sorted, (0..20).collect::<Vec<_>>());
+
+        // The playing song is left out of the deal, and the songs are dealt again on repeat.
+        let mut shuffle = Shuffle::new(Some(7));
+        let dealt: Vec<String> = (0..19)
+            .map(|_| shuffle.next(&songs, Some(&songs[3]), true).unwrap().name)
+            .collect();
+        assert!(!dealt.contains(&String::from("3")));
+        assert!(shuffle.next(&songs, None, false).is_none());
+        assert!(shuffle.next(&songs, None, true).is_some());
+    }
+}
@@ -0,0 +1,517 @@
+use color_eyre::Result;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, oneshot};
+use zbus::{
+    connection, fdo, interface,
+    object_server::SignalContext,
+    zvariant::{ObjectPath, OwnedValue, Value},
+    Connection,
+};
+
+use crate::app::{App, Playback, Song};
+use crate::ipc::{self, Pending, Request};
+use crate::volume::Volume;
+
+/// The bus name, a second player gets `NAME.instance<pid>` instead.
+const NAME: &str = "org.mpris.MediaPlayer2.rmu";
+const PATH: &str = "/org/mpris/MediaPlayer2";
+
+/// What the desktop sees of the player, refreshed by the main loop.
+struct Shared {
+    playback: Playback,
+    song: Option<Song>,
+    /// Changes with every song, so the track id of `Metadata` does too.
+    track: u64,
+    /// In microseconds.
+    position: i64,
+    /// The gain of the volume curve, so the desktop shows the loudness actually played.
+    volume: f64,
+}
+
+impl Shared {
+    fn track_id(&self) -> ObjectPath<'static> {
+        ObjectPath::try_from(format!("/org/rmu/track/{}", self.track)).unwrap()
+    }
+}
+
+/// The `org.mpris.MediaPlayer2` interface, the player can not be raised or quit.
+struct Root;
+
+#[interface(name = "org.mpris.MediaPlayer2")]
+impl Root {
+    fn raise(&self) {}
+
+    fn quit(&self) {}
+
+    #[zbus(property)]
+    fn can_quit(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn can_raise(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn identity(&self) -> &str {
+        "rmu"
+    }
+
+    #[zbus(property)]
+    fn supported_uri_schemes(&self) -> Vec<&str> {
+        vec!["file"]
+    }
+
+    #[zbus(property)]
+    fn supported_mime_types(&self) -> Vec<&str> {
+        vec![
+            "audio/mpeg",
+            "audio/mp4",
+            "audio/aac",
+            "audio/flac",
+            "audio/ogg",
+            "audio/wav",
+            "audio/aiff",
+        ]
+    }
+}
+
+/// The `org.mpris.MediaPlayer2.Player` interface, calls are run by the main loop like the
+/// requests of the control socket.
+struct Player {
+    tx: mpsc::UnboundedSender<Pending>,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Player {
+    async fn request(&self, request: Request) -> fdo::Result<()> {
+        let (reply, response) = oneshot::channel();
+        let failed = |e: &dyn std::error::Error| fdo::Error::Failed(e.to_string());
+        self.tx.send((request, reply)).map_err(|e| failed(&e))?;
+        let response = response.await.map_err(|e| failed(&e))?;
+        match response.error {
+            Some(error) => Err(fdo::Error::Failed(error)),
+            None => Ok(()),
+        }
+    }
+
+    /// Seek and tell the desktop where the song is now.
+    async fn seek_to(&self, request: Request, ctxt: &SignalContext<'_>) -> fdo::Result<()> {
+        self.request(request).await?;
+        let (reply, response) = oneshot::channel();
+        let _ = self.tx.send((Request::Status, reply));
+        let position = response
+            .await
+            .ok()
+            .and_then(|response| response.status?["position"].as_f64())
+            .unwrap_or_default();
+        Player::seeked(ctxt, micros(position)).await?;
+        Ok(())
+    }
+
+    fn shared(&self) -> std::sync::MutexGuard<'_, Shared> {
+        self.shared.lock().unwrap()
+    }
+}
+
+#[interface(name = "org.mpris.MediaPlayer2.Player")]
+impl Player {
+    async fn play_pause(&self) -> fdo::Result<()> {
+        self.request(Request::Toggle).await
+    }
+
+    async fn play(&self) -> fdo::Result<()> {
+        self.request(Request::Play { path: None }).await
+    }
+
+    async fn pause(&self) -> fdo::Result<()> {
+        self.request(Request::Pause).await
+    }
+
+    async fn stop(&self) -> fdo::Result<()> {
+        self.request(Request::Stop).await
+    }
+
+    async fn next(&self) -> fdo::Result<()> {
+        self.request(Request::Next).await
+    }
+
+    async fn previous(&self) -> fdo::Result<()> {
+        self.request(Request::Previous).await
+    }
+
+    /// Seek by `offset` microseconds.
+    async fn seek(
+        &self,
+        offset: i64,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> fdo::Result<()> {
+        let request = Request::Seek {
+            position: None,
+            offset: Some(offset as f64 / 1e6),
+        };
+        self.seek_to(request, &ctxt).await
+    }
+
+    /// Seek to `position` microseconds, unless another song is playing by now.
+    async fn set_position(
+        &self,
+        track_id: ObjectPath<'_>,
+        position: i64,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> fdo::Result<()> {
+        if track_id != self.shared().track_id() || position < 0 {
+            return Ok(());
+        }
+        let request = Request::Seek {
+            position: Some(position as f64 / 1e6),
+            offset: None,
+        };
+        self.seek_to(request, &ctxt).await
+    }
+
+    #[zbus(signal)]
+    async fn seeked(ctxt: &SignalContext<'_>, position: i64) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn playback_status(&self) -> &str {
+        match self.shared().playback {
+            Playback::Stopped => "Stopped",
+            Playback::Paused => "Paused",
+            Playback::Playing => "Playing",
+        }
+    }
+
+    #[zbus(property)]
+    fn metadata(&self) -> HashMap<String, OwnedValue> {
+        let shared = self.shared();
+        let Some(song) = &shared.song else {
+            return HashMap::new();
+        };
+        let mut metadata = HashMap::new();
+        let mut insert = |key: &str, value: Value| {
+            if let Ok(value) = value.try_to_owned() {
+                metadata.insert(key.to_string(), value);
+            }
+        };
+        insert("mpris:trackid", shared.track_id().into());
+        insert("mpris:length", micros(song.time).into());
+        insert("xesam:title", song.title().into());
+        insert("xesam:url", file_url(&song.name).into());
+        if let Some(artist) = &song.tags.artist {
+            insert("xesam:artist", vec![artist.as_str()].into());
+        }
+        if let Some(album) = &song.tags.album {
+            insert("xesam:album", album.as_str().into());
+        }
+        if let Some(album_artist) = &song.tags.album_artist {
+            insert("xesam:albumArtist", vec![album_artist.as_str()].into());
+        }
+        if let Some(track) = song.tags.track {
+            insert("xesam:trackNumber", (track as i32).into());
+        }
+        if let Some(genre) = &song.tags.genre {
+            insert("xesam:genre", vec![genre.as_str()].into());
+        }
+        metadata
+    }
+
+    #[zbus(property)]
+    fn volume(&self) -> f64 {
+        self.shared().volume
+    }
+
+    #[zbus(property)]
+    async fn set_volume(&self, volume: f64) -> zbus::Result<()> {
+        let level = Volume::level_for_gain(volume);
+        self.request(Request::Volume {
+            level: Some(level),
+            change: None,
+        })
+        .await
+        .map_err(Into::into)
+    }
+
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn position(&self) -> i64 {
+        self.shared().position
+    }
+
+    #[zbus(property)]
+    fn rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn minimum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn maximum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn can_go_next(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_go_previous(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_play(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_pause(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_seek(&self) -> bool {
+        true
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+fn micros(secs: f64) -> i64 {
+    (secs * 1e6) as i64
+}
+
+/// The `file://` URL of a local path, bytes other than letters, digits, `-._~` and `/` are
+/// percent-encoded.
+pub fn file_url(path: &str) -> String {
+    let mut url = String::from("file://");
+    for &byte in path.as_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                url.push(byte as char)
+            }
+            _ => url.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    url
+}
+
+/// `Mpris` exposes the player on D-Bus, so media keys and desktop widgets can control it.
+pub struct Mpris {
+    connection: Connection,
+    rx: mpsc::UnboundedReceiver<Pending>,
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Mpris {
+    /// Serve on the session bus.
+    pub async fn new() -> Result<Self> {
+        Self::serve(connection::Builder::session()?).await
+    }
+
+    async fn serve(builder: connection::Builder<'_>) -> Result<Self> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let shared = Arc::new(Mutex::new(Shared {
+            playback: Playback::Stopped,
+            song: None,
+            track: 0,
+            position: 0,
+            volume: 1.0,
+        }));
+        let player = Player {
+            tx,
+            shared: shared.clone(),
+        };
+        let connection = builder
+            .serve_at(PATH, Root)?
+            .serve_at(PATH, player)?
+            .build()
+            .await?;
+        match connection.request_name(NAME).await {
+            Err(zbus::Error::NameTaken) => {
+                let name = format!("{}.instance{}", NAME, std::process::id());
+                connection.request_name(name).await?
+            }
+            result => result?,
+        }
+        Ok(Self {
+            connection,
+            rx,
+            shared,
+        })
+    }
+
+    /// Run the calls received since the last update, then tell the desktop what has changed.
+    pub async fn update(&mut self, app: &mut App<'_>) -> Result<()> {
+        while let Ok((request, reply)) = self.rx.try_recv() {
+            let _ = reply.send(ipc::handle(app, request));
+        }
+
+        // Zero when muted.
+        let volume = f64::from(app.volume.gain());
+        let (playback, song, volume) = {
+            let mut shared = self.shared.lock().unwrap();
+            shared.position = micros(app.sink.get_pos().as_secs_f64());
+            let playback = shared.playback != app.playback();
+            let song = shared.song.as_ref().map(|song| &song.name)
+                != app.current.as_ref().map(|song| &song.name);
+            let changed = (playback, song, shared.volume != volume);
+            shared.playback = app.playback();
+            shared.volume = volume;
+            if song {
+                shared.song = app.current.clone();
+                shared.track += 1;
+            }
+            changed
+        };
+        if !(playback || song || volume) {
+            return Ok(());
+        }
+
+        let player = self
+            .connection
+            .object_server()
+            .interface::<_, Player>(PATH)
+            .await?;
+        let ctxt = player.signal_context();
+        let player = player.get().await;
+        if playback {
+            player.playback_status_changed(ctxt).await?;
+        }
+        if song {
+            player.metadata_changed(ctxt).await?;
+        }
+        if volume {
+            player.volume_changed(ctxt).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::process::{Command, Stdio};
+    use std::time::{Duration, Instant};
+    use zbus::{proxy::CacheProperties, Proxy};
+
+    #[tokio::test]
+    #[ignore = "needs dbus-daemon, run with `cargo test -- --ignored`"]
+    async fn test_mpris() {
+        // A private bus, the desktop of whoever runs the tests is left alone.
+        let bus = testing::temp_dir("mpris");
+        let address = format!("unix:path={}", bus.join("bus").display());
+        let mut daemon = Command::new("dbus-daemon")
+            .args(["--session", "--nofork", "--address", &address])
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("dbus-daemon not found");
+        let start = Instant::now();
+        while !bus.join("bus").exists() {
+            if start.elapsed() > Duration::from_secs(5) {
+                let _ = daemon.kill();
+                panic!("dbus-daemon did not create the bus");
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        // A name which has to be percent-encoded in the URL.
+        let a = bus.join("a #1.wav");
+        testing::write_wav(&a, 5);
+        testing::write_wav(&bus.join("b.wav"), 5);
+        let mut app = testing::fake_app(&bus);
+        let mut mpris = Mpris::serve(connection::Builder::address(address.as_str()).unwrap())
+            .await
+            .unwrap();
+        // A second player can not take the name, it gets one of its own.
+        let second = Mpris::serve(connection::Builder::address(address.as_str()).unwrap())
+            .await
+            .unwrap();
+        let dbus = fdo::DBusProxy::new(&second.connection).await.unwrap();
+        let instance = format!("{}.instance{}", NAME, std::process::id());
+        let instance = instance.as_str().try_into().unwrap();
+        assert!(dbus.name_has_owner(instance).await.unwrap());
+        drop(second);
+
+        let client = tokio::spawn(async move {
+            let connection = connection::Builder::address(address.as_str())
+                .unwrap()
+                .build()
+                .await
+                .unwrap();
+            let player = zbus::proxy::Builder::<Proxy>::new(&connection)
+                .destination(NAME)
+                .unwrap()
+                .path(PATH)
+                .unwrap()
+                .interface("org.mpris.MediaPlayer2.Player")
+                .unwrap()
+                .cache_properties(CacheProperties::No)
+                .build()
+                .await
+                .unwrap();
+            let status = || async {
+                player
+                    .get_property::<String>("PlaybackStatus")
+                    .await
+                    .unwrap()
+            };
+            assert_eq!(status().await, "Stopped");
+
+            player.call_method("PlayPause", &()).await.unwrap();
+            // The properties follow the main loop.
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert_eq!(status().await, "Playing");
+            let mut metadata: HashMap<String, OwnedValue> =
+                player.get_property("Metadata").await.unwrap();
+            let url = String::try_from(metadata.remove("xesam:url").unwrap()).unwrap();
+            assert!(url.ends_with("/a%20%231.wav"), "{}", url);
+            assert_eq!(
+                i64::try_from(metadata.remove("mpris:length").unwrap()).unwrap(),
+                5_000_000
+            );
+
+            player.call_method("Seek", &(2_000_000i64)).await.unwrap();
+            player.set_property("Volume", 0.25).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert!(player.get_property::<i64>("Position").await.unwrap() >= 2_000_000);
+            // The level closest to the gain asked for.
+            let volume = player.get_property::<f64>("Volume").await.unwrap();
+            assert!((volume - 0.25).abs() < 0.01, "{}", volume);
+
+            player.call_method("Next", &()).await.unwrap();
+            player.call_method("PlayPause", &()).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert_eq!(status().await, "Paused");
+            let mut metadata: HashMap<String, OwnedValue> =
+                player.get_property("Metadata").await.unwrap();
+            let url = String::try_from(metadata.remove("xesam:url").unwrap()).unwrap();
+            assert!(url.ends_with("b.wav"));
+        });
+
+        while !client.is_finished() {
+            mpris.update(&mut app).await.unwrap();
+            app.update(Duration::from_millis(10));
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        let result = client.await;
+        daemon.kill().unwrap();
+        daemon.wait().unwrap();
+        std::fs::remove_dir_all(&bus).unwrap();
+        result.unwrap();
+    }
+}
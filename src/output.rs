@@ -0,0 +1,40 @@
+use rodio::{OutputStreamHandle, Sink};
+
+use crate::error::Error;
+
+/// `Output` is where the sinks play.
+#[derive(Clone)]
+pub enum Output {
+    /// The sound card.
+    Device(OutputStreamHandle),
+    /// A fake sound card for tests, it plays in real time and throws the samples away.
+    #[cfg(test)]
+    Fake,
+}
+
+impl Output {
+    /// Create a new sink playing on this output.
+    pub fn sink(&self) -> Result<Sink, Error> {
+        match self {
+            Output::Device(handle) => Ok(Sink::try_new(handle)?),
+            #[cfg(test)]
+            Output::Fake => {
+                use rodio::Source;
+                use std::time::Duration;
+
+                let (sink, mut output) = Sink::new_idle();
+                // Pull the samples of every 10ms, until the sink is dropped.
+                std::thread::spawn(move || loop {
+                    let samples = output.sample_rate() as usize * output.channels() as usize / 100;
+                    for _ in 0..samples.max(1) {
+                        if output.next().is_none() {
+                            return;
+                        }
+                    }
+                    std::thread::sleep(Duration::from_millis(10));
+                });
+                Ok(sink)
+            }
+        }
+    }
+}
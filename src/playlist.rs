@@ -0,0 +1,737 @@
+use color_eyre::{eyre::eyre, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use tokio::sync::oneshot;
+
+use crate::app::Song;
+use crate::config;
+use crate::library;
+use crate::meta::Tags;
+use crate::ui::StatefulList;
+
+/// Saved playlists are M3U8 files with this extension.
+const EXTENSION: &str = "m3u8";
+
+/// A song of a playlist, with the length and title of its `#EXTINF` line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track {
+    pub path: PathBuf,
+    pub time: Option<f64>,
+    pub title: Option<String>,
+}
+
+impl Track {
+    fn new(song: &Song) -> Self {
+        Self {
+            path: PathBuf::from(&song.name),
+            time: Some(song.time),
+            title: Some(song.display()),
+        }
+    }
+}
+
+impl Track {
+    /// The song shown until the file is read, from the `#EXTINF` line.
+    fn song(&self) -> Song {
+        Song {
+            name: self.path.to_string_lossy().to_string(),
+            rel_path: self.path.file_name().map(PathBuf::from).unwrap_or_default(),
+            time: self.time.unwrap_or_default(),
+            tags: Tags {
+                title: self.title.clone(),
+                ..Tags::default()
+            },
+            unplayable: false,
+            added: 0,
+        }
+    }
+}
+
+/// Read the songs in a background thread, a file which can not be decoded is unplayable. It stops
+/// early once the songs are not wanted anymore.
+fn read_songs(paths: Vec<PathBuf>) -> oneshot::Receiver<Vec<Song>> {
+    let (tx, rx) = oneshot::channel();
+    tokio::task::spawn_blocking(move || {
+        let mut songs = Vec::new();
+        for path in paths {
+            if tx.is_closed() {
+                return;
+            }
+            songs.push(library::read_song(&path));
+        }
+        let _ = tx.send(songs);
+    });
+    rx
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Playlist {
+    pub name: String,
+    pub tracks: Vec<Track>,
+}
+
+/// The directory of the saved playlists, `$XDG_CONFIG_HOME/rmu/playlists`.
+pub fn dir() -> Result<PathBuf> {
+    let mut path = config::xdg_dir("XDG_CONFIG_HOME", ".config")?;
+    path.push("playlists");
+    Ok(path)
+}
+
+/// Read a playlist file, PLS by its extension and M3U otherwise. Relative paths are resolved
+/// against the directory of the file.
+pub fn read(path: &Path) -> Result<Vec<Track>> {
+    let bytes = fs::read(path).map_err(|e| eyre!("Can not read `{}`: {}", path.display(), e))?;
+    // Plain M3U files are often Latin-1, the broken chars are only shown, never matched.
+    let text = String::from_utf8_lossy(&bytes);
+    let base = std::path::absolute(path)?;
+    let base = base.parent().unwrap_or(Path::new("/"));
+    Ok(match is_pls(path) {
+        true => parse_pls(&text, base),
+        false => parse_m3u(&text, base),
+    })
+}
+
+/// Write a playlist file, PLS by its extension and M3U8 otherwise. Songs under the directory of
+/// the file are written relative to it, so the directory can be moved around.
+pub fn write(path: &Path, tracks: &[Track]) -> Result<()> {
+    let base = std::path::absolute(path)?;
+    let base = base.parent().unwrap_or(Path::new("/"));
+    let text = match is_pls(path) {
+        true => to_pls(tracks, base),
+        false => to_m3u(tracks, base),
+    };
+    fs::write(path, text).map_err(|e| eyre!("Can not write `{}`: {}", path.display(), e))
+}
+
+fn is_pls(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("pls"))
+}
+
+/// Parse an M3U or M3U8 playlist, relative paths are resolved against `base`.
+fn parse_m3u(text: &str, base: &Path) -> Vec<Track> {
+    let mut tracks = Vec::new();
+    let mut info = None;
+    for line in text.trim_start_matches('\u{feff}').lines().map(str::trim) {
+        if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            info = Some(parse_extinf(extinf));
+        } else if line.is_empty() || line.starts_with('#') {
+            continue;
+        } else {
+            let (time, title) = info.take().unwrap_or_default();
+            if let Some(path) = resolve(line, base) {
+                tracks.push(Track { path, time, title });
+            }
+        }
+    }
+    tracks
+}
+
+/// Parse `<length>[ attributes],<title>`, a length of `-1` is unknown.
+fn parse_extinf(extinf: &str) -> (Option<f64>, Option<String>) {
+    let (time, title) = extinf.split_once(',').unwrap_or((extinf, ""));
+    let time = time
+        .split_whitespace()
+        .next()
+        .and_then(|time| time.parse().ok())
+        .filter(|&time: &f64| time >= 0.0);
+    let title = Some(title.trim())
+        .filter(|title| !title.is_empty())
+        .map(String::from);
+    (time, title)
+}
+
+/// Parse a PLS playlist, the entries are ordered by their number.
+fn parse_pls(text: &str, base: &Path) -> Vec<Track> {
+    let mut files = BTreeMap::new();
+    let mut titles = BTreeMap::new();
+    let mut times = BTreeMap::new();
+    for line in text.lines() {
+        let Some((key, value)) = line.trim().split_once('=') else {
+            continue;
+        };
+        let key = key.trim().to_ascii_lowercase();
+        let (field, n) = key.split_at(key.find(|c: char| c.is_ascii_digit()).unwrap_or(0));
+        let Ok(n) = n.parse::<u32>() else {
+            continue;
+        };
+        let value = value.trim().to_string();
+        let map = match field {
+            "file" => &mut files,
+            "title" => &mut titles,
+            "length" => &mut times,
+            _ => continue,
+        };
+        map.insert(n, value);
+    }
+    files
+        .into_iter()
+        .filter_map(|(n, file)| {
+            Some(Track {
+                path: resolve(&file, base)?,
+                time: times
+                    .get(&n)
+                    .and_then(|time| time.parse().ok())
+                    .filter(|&time: &f64| time >= 0.0),
+                title: titles.remove(&n).filter(|title| !title.is_empty()),
+            })
+        })
+        .collect()
+}
+
+/// Turn an entry of a playlist into a local path, URLs other than `file://` can not be played.
+fn resolve(entry: &str, base: &Path) -> Option<PathBuf> {
+    let path = if let Some(url) = entry.strip_prefix("file://") {
+        PathBuf::from(percent_decode(url.strip_prefix("localhost").unwrap_or(url)))
+    } else if entry.contains("://") {
+        return None;
+    } else if !entry.starts_with('/') {
+        // Playlists written on Windows separate relative paths with backslashes.
+        PathBuf::from(entry.replace('\\', "/"))
+    } else {
+        PathBuf::from(entry)
+    };
+    Some(normalize(&base.join(path)))
+}
+
+/// Remove the `.` and `..` of the path without touching the filesystem, so the path is the same
+/// as in library.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = s.get(i + 1..i + 3).filter(|_| bytes[i] == b'%');
+        match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn to_m3u(tracks: &[Track], base: &Path) -> String {
+    let mut text = String::from("#EXTM3U\n");
+    for track in tracks {
+        if track.time.is_some() || track.title.is_some() {
+            let time = track.time.map_or(-1, |time| time.round() as i64);
+            let title = track.title.as_deref().unwrap_or_default();
+            text.push_str(&format!("#EXTINF:{},{}\n", time, title));
+        }
+        let path = track.path.strip_prefix(base).unwrap_or(&track.path);
+        text.push_str(&format!("{}\n", path.display()));
+    }
+    text
+}
+
+fn to_pls(tracks: &[Track], base: &Path) -> String {
+    let mut text = String::from("[playlist]\n");
+    for (i, track) in tracks.iter().enumerate() {
+        let n = i + 1;
+        let path = track.path.strip_prefix(base).unwrap_or(&track.path);
+        text.push_str(&format!("File{}={}\n", n, path.display()));
+        if let Some(title) = &track.title {
+            text.push_str(&format!("Title{}={}\n", n, title));
+        }
+        let time = track.time.map_or(-1, |time| time.round() as i64);
+        text.push_str(&format!("Length{}={}\n", n, time));
+    }
+    text.push_str(&format!("NumberOfEntries={}\nVersion=2\n", tracks.len()));
+    text
+}
+
+/// A playlist name is its file name, so it can not be a path.
+fn check_name(name: &str) -> Result<&str> {
+    let name = name.trim();
+    if name.is_empty() || name.starts_with('.') || name.contains('/') {
+        return Err(eyre!("Invalid playlist name `{}`", name));
+    }
+    Ok(name)
+}
+
+/// `Playlists` holds the saved playlists and the songs of the selected one, shown side by side in
+/// the playlists tab.
+pub struct Playlists {
+    dir: PathBuf,
+    pub lists: StatefulList<Playlist>,
+    /// Songs of the selected playlist.
+    pub songs: StatefulList<Song>,
+    /// Whether the songs have the focus, else the playlists.
+    pub focus_songs: bool,
+    /// Name and length of the playlist `songs` were resolved for.
+    shown: Option<(String, usize)>,
+    /// Songs read from files outside the library, so they are only read once.
+    read: HashMap<PathBuf, Song>,
+    /// The songs outside the library being read in background.
+    reading: Option<oneshot::Receiver<Vec<Song>>>,
+    /// The songs have to be resolved again, the library changed or songs were read.
+    stale: bool,
+}
+
+impl Playlists {
+    /// Load all playlists of `dir`, unreadable ones are skipped.
+    pub fn load(dir: PathBuf) -> Self {
+        let mut lists: Vec<Playlist> = fs::read_dir(&dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == EXTENSION))
+            .filter_map(|path| {
+                Some(Playlist {
+                    name: path.file_stem()?.to_string_lossy().to_string(),
+                    tracks: read(&path).ok()?,
+                })
+            })
+            .collect();
+        lists.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut lists = StatefulList::with_items(lists);
+        lists.first();
+        Self {
+            dir,
+            lists,
+            songs: StatefulList::with_items(Vec::new()),
+            focus_songs: false,
+            shown: None,
+            read: HashMap::new(),
+            reading: None,
+            stale: false,
+        }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", name, EXTENSION))
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.lists.items.iter().position(|list| list.name == name)
+    }
+
+    pub fn selected(&self) -> Option<&Playlist> {
+        self.lists.items.get(self.lists.state.selected()?)
+    }
+
+    fn save(&self, playlist: &Playlist) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        write(&self.path(&playlist.name), &playlist.tracks)
+    }
+
+    /// Add the playlist in name order and select it.
+    fn insert(&mut self, playlist: Playlist) -> Result<()> {
+        self.save(&playlist)?;
+        let name = playlist.name.clone();
+        self.lists.items.push(playlist);
+        self.lists.items.sort_by(|a, b| a.name.cmp(&b.name));
+        self.lists.state.select(self.position(&name));
+        Ok(())
+    }
+
+    /// Create an empty playlist.
+    pub fn create(&mut self, name: &str) -> Result<()> {
+        let name = check_name(name)?;
+        if self.position(name).is_some() {
+            return Err(eyre!("Playlist `{}` already exists", name));
+        }
+        self.insert(Playlist {
+            name: name.to_string(),
+            tracks: Vec::new(),
+        })
+    }
+
+    /// Rename the selected playlist.
+    pub fn rename(&mut self, name: &str) -> Result<()> {
+        let name = check_name(name)?;
+        let i = self
+            .lists
+            .state
+            .selected()
+            .ok_or_else(|| eyre!("No playlist"))?;
+        if self.position(name).is_some() {
+            return Err(eyre!("Playlist `{}` already exists", name));
+        }
+        let old = self.path(&self.lists.items[i].name);
+        fs::rename(old, self.path(name))?;
+        let mut playlist = self.lists.items.remove(i);
+        playlist.name = name.to_string();
+        self.lists.items.push(playlist);
+        self.lists.items.sort_by(|a, b| a.name.cmp(&b.name));
+        self.lists.state.select(self.position(name));
+        Ok(())
+    }
+
+    /// Delete the selected playlist and its file.
+    pub fn delete(&mut self) -> Result<()> {
+        let i = self
+            .lists
+            .state
+            .selected()
+            .ok_or_else(|| eyre!("No playlist"))?;
+        fs::remove_file(self.path(&self.lists.items[i].name))?;
+        self.lists.items.remove(i);
+        let len = self.lists.items.len();
+        self.lists.state.select((len > 0).then(|| i.min(len - 1)));
+        Ok(())
+    }
+
+    /// Add the songs to the end of the playlist, it is created if needed.
+    pub fn add(&mut self, name: &str, songs: &[Song]) -> Result<()> {
+        let name = check_name(name)?;
+        let tracks = songs.iter().map(Track::new);
+        match self.position(name) {
+            Some(i) => {
+                self.lists.items[i].tracks.extend(tracks);
+                self.save(&self.lists.items[i])
+            }
+            None => self.insert(Playlist {
+                name: name.to_string(),
+                tracks: tracks.collect(),
+            }),
+        }
+    }
+
+    /// Remove the selected song from the selected playlist.
+    pub fn remove_song(&mut self) -> Result<()> {
+        let (Some(i), Some(song)) = (self.lists.state.selected(), self.songs.state.selected())
+        else {
+            return Ok(());
+        };
+        let Some(playlist) = self.lists.items.get_mut(i) else {
+            return Ok(());
+        };
+        if song >= playlist.tracks.len() {
+            return Ok(());
+        }
+        playlist.tracks.remove(song);
+        self.songs.items.remove(song);
+        self.shown = Some((playlist.name.clone(), playlist.tracks.len()));
+        let len = self.songs.items.len();
+        self.songs
+            .state
+            .select((len > 0).then(|| song.min(len - 1)));
+        self.save(&self.lists.items[i])
+    }
+
+    /// Copy an M3U, M3U8 or PLS file into the saved playlists, returns the name it got.
+    pub fn import(&mut self, path: &Path) -> Result<String> {
+        let tracks = read(path)?;
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let stem = check_name(&stem).unwrap_or("imported").to_string();
+        let name = (1..)
+            .map(|n| match n {
+                1 => stem.clone(),
+                n => format!("{} ({})", stem, n),
+            })
+            .find(|name| self.position(name).is_none())
+            .unwrap_or(stem);
+        self.insert(Playlist {
+            name: name.clone(),
+            tracks,
+        })?;
+        Ok(name)
+    }
+
+    /// Write the selected playlist to `path`, as PLS if it ends with `.pls`.
+    pub fn export(&self, path: &Path) -> Result<()> {
+        let playlist = self.selected().ok_or_else(|| eyre!("No playlist"))?;
+        write(path, &playlist.tracks)
+    }
+
+    /// Resolve the songs again on the next refresh, the library changed. The songs read from
+    /// files are dropped too, the library may have them now.
+    pub fn invalidate(&mut self) {
+        self.read.clear();
+        self.reading = None;
+        self.stale = true;
+    }
+
+    /// Keep the songs read in background, returns whether there are new ones.
+    fn poll_reading(&mut self) -> bool {
+        let Some(rx) = &mut self.reading else {
+            return false;
+        };
+        let songs = match rx.try_recv() {
+            Ok(songs) => songs,
+            Err(oneshot::error::TryRecvError::Empty) => return false,
+            Err(oneshot::error::TryRecvError::Closed) => Vec::new(),
+        };
+        self.reading = None;
+        self.read.extend(
+            songs
+                .into_iter()
+                .map(|song| (PathBuf::from(&song.name), song)),
+        );
+        true
+    }
+
+    /// Show the songs of the selected playlist. Songs of the library keep their tags, others are
+    /// shown from the playlist entry until read from the file in background, missing files are
+    /// unplayable. The focus and selection are kept if the same playlist only changed.
+    pub fn refresh(&mut self, library: &[Song]) {
+        if self.poll_reading() {
+            self.stale = true;
+        }
+        let shown = self
+            .selected()
+            .map(|playlist| (playlist.name.clone(), playlist.tracks.len()));
+        if shown == self.shown && !self.stale {
+            return;
+        }
+        self.stale = false;
+        let by_name: HashMap<&str, &Song> = library
+            .iter()
+            .map(|song| (song.name.as_str(), song))
+            .collect();
+        let tracks = self
+            .lists
+            .state
+            .selected()
+            .and_then(|i| self.lists.items.get(i))
+            .map(|playlist| playlist.tracks.as_slice())
+            .unwrap_or_default();
+        let mut missing = Vec::new();
+        self.songs.items = tracks
+            .iter()
+            .map(|track| {
+                if let Some(&song) = by_name.get(track.path.to_string_lossy().as_ref()) {
+                    return song.clone();
+                }
+                if let Some(song) = self.read.get(&track.path) {
+                    return song.clone();
+                }
+                missing.push(track.path.clone());
+                track.song()
+            })
+            .collect();
+        // The songs of a playlist no longer shown are not needed anymore.
+        self.reading = (!missing.is_empty()).then(|| read_songs(missing));
+
+        let same =
+            shown.as_ref().map(|(name, _)| name) == self.shown.as_ref().map(|(name, _)| name);
+        let len = self.songs.items.len();
+        if same && self.focus_songs && len > 0 {
+            let selected = self.songs.state.selected().unwrap_or(0);
+            self.songs.state.select(Some(selected.min(len - 1)));
+        } else {
+            self.songs.state.select(None);
+            self.focus_songs = false;
+        }
+        self.shown = shown;
+    }
+
+    /// Move the focus between the playlists and their songs, returns false if it can not move.
+    pub fn focus(&mut self, songs: bool) -> bool {
+        if songs == self.focus_songs || (songs && self.songs.items.is_empty()) {
+            return false;
+        }
+        self.focus_songs = songs;
+        self.songs.state.select(songs.then_some(0));
+        true
+    }
+
+    pub fn previous(&mut self) {
+        match self.focus_songs {
+            true => self.songs.previous(),
+            false if !self.lists.items.is_empty() => self.lists.previous(),
+            false => {}
+        }
+    }
+
+    pub fn next(&mut self) {
+        match self.focus_songs {
+            true => self.songs.next(),
+            false if !self.lists.items.is_empty() => self.lists.next(),
+            false => {}
+        }
+    }
+
+    pub fn first(&mut self) {
+        match self.focus_songs {
+            true => self.songs.first(),
+            false => self.lists.first(),
+        }
+    }
+
+    pub fn last(&mut self) {
+        match self.focus_songs {
+            true => self.songs.last(),
+            false => self.lists.last(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{mpris::file_url, testing};
+
+    fn track(path: &str, time: Option<f64>, title: Option<&str>) -> Track {
+        Track {
+            path: PathBuf::from(path),
+            time,
+            title: title.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        let m3u = "\u{feff}#EXTM3U\n\
+            #EXTINF:215,Artist - Title\n\
+            01 Track.mp3\n\
+            \n\
+            ../Other/02%20Track.mp3\n\
+            Sub\\03 Track.flac\n\
+            /abs/04 Track.ogg\n\
+            file:///abs/05%20Track.m4a\n\
+            #EXTINF:-1,Radio\n\
+            http://radio.example/stream\n";
+        assert_eq!(
+            parse_m3u(m3u, Path::new("/music/List")),
+            [
+                track(
+                    "/music/List/01 Track.mp3",
+                    Some(215.0),
+                    Some("Artist - Title")
+                ),
+                track("/music/Other/02%20Track.mp3", None, None),
+                track("/music/List/Sub/03 Track.flac", None, None),
+                track("/abs/04 Track.ogg", None, None),
+                track("/abs/05 Track.m4a", None, None),
+            ]
+        );
+
+        let pls = "[playlist]\n\
+            File2=b.mp3\n\
+            Title2=B\n\
+            File1=/abs/a.mp3\n\
+            Length1=61\n\
+            Length2=-1\n\
+            NumberOfEntries=2\n";
+        assert_eq!(
+            parse_pls(pls, Path::new("/music")),
+            [
+                track("/abs/a.mp3", Some(61.0), None),
+                track("/music/b.mp3", None, Some("B")),
+            ]
+        );
+
+        let tracks = [
+            track("/music/a.mp3", Some(61.0), Some("A")),
+            track("/elsewhere/b.mp3", None, None),
+        ];
+        let base = Path::new("/music");
+        assert_eq!(parse_m3u(&to_m3u(&tracks, base), base), tracks);
+        assert_eq!(
+            to_m3u(&tracks, base),
+            "#EXTM3U\n#EXTINF:61,A\na.mp3\n/elsewhere/b.mp3\n"
+        );
+        let tracks = [track("/music/a.mp3", Some(61.0), Some("A"))];
+        assert_eq!(parse_pls(&to_pls(&tracks, base), base), tracks);
+
+        let url = file_url("/music/Ab #1 100%/é.mp3");
+        assert_eq!(url, "file:///music/Ab%20%231%20100%25/%C3%A9.mp3");
+        assert_eq!(
+            parse_m3u(&url, base),
+            [track("/music/Ab #1 100%/é.mp3", None, None)]
+        );
+    }
+
+    /// Refresh until the songs outside the library are read.
+    async fn refreshed(playlists: &mut Playlists, library: &[Song]) {
+        playlists.refresh(library);
+        while playlists.reading.is_some() {
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            playlists.refresh(library);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_playlists() {
+        let dir = testing::temp_dir("playlists");
+        let saved = dir.join("saved");
+        testing::write_wav(&dir.join("a.wav"), 1);
+        let songs = library::read_paths(&[dir.join("a.wav")], &Default::default()).unwrap();
+
+        let mut playlists = Playlists::load(saved.clone());
+        playlists.add("Mix", &songs).unwrap();
+        playlists.create("Empty").unwrap();
+        assert!(playlists.create("Mix").is_err());
+        assert!(playlists.create("../evil").is_err());
+        assert_eq!(playlists.selected().unwrap().name, "Empty");
+        playlists.rename("Blank").unwrap();
+
+        std::fs::write(dir.join("old.pls"), "File1=a.wav\nFile2=missing.mp3\n").unwrap();
+        assert_eq!(playlists.import(&dir.join("old.pls")).unwrap(), "old");
+        assert_eq!(playlists.import(&dir.join("old.pls")).unwrap(), "old (2)");
+        playlists.delete().unwrap();
+
+        let mut playlists = Playlists::load(saved.clone());
+        let names: Vec<&str> = playlists
+            .lists
+            .items
+            .iter()
+            .map(|l| l.name.as_str())
+            .collect();
+        assert_eq!(names, ["Blank", "Mix", "old"]);
+        playlists.lists.state.select(Some(2));
+        // Shown from the playlist until read.
+        playlists.refresh(&[]);
+        assert!(!playlists.songs.items[1].unplayable);
+        refreshed(&mut playlists, &[]).await;
+        assert!(playlists.songs.items[1].unplayable);
+        // The library has the song now, with its tags.
+        let mut library = songs.clone();
+        library[0].tags.artist = Some(String::from("Artist"));
+        playlists.refresh(&library);
+        assert_eq!(playlists.songs.items[0].tags.artist, None);
+        playlists.invalidate();
+        refreshed(&mut playlists, &library).await;
+        assert_eq!(
+            playlists.songs.items[0].tags.artist.as_deref(),
+            Some("Artist")
+        );
+        assert!(playlists.songs.items[1].unplayable);
+
+        // Adding to the open playlist keeps the focus on its songs.
+        assert!(playlists.focus(true));
+        playlists.songs.next();
+        playlists.add("old", &songs).unwrap();
+        refreshed(&mut playlists, &songs).await;
+        assert!(playlists.focus_songs);
+        assert_eq!(playlists.songs.state.selected(), Some(1));
+        assert_eq!(playlists.songs.items.len(), 3);
+        playlists.songs.next();
+        playlists.remove_song().unwrap();
+        playlists.songs.state.select(Some(0));
+        playlists.remove_song().unwrap();
+        playlists.export(&dir.join("out.m3u")).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.join("out.m3u")).unwrap(),
+            "#EXTM3U\nmissing.mp3\n"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,141 @@
+use crate::{app::Song, ui::StatefulList};
+
+/// `Queue` holds the songs to play next, independent of the library order.
+pub struct Queue {
+    pub songs: StatefulList<Song>,
+}
+
+impl Queue {
+    pub fn new() -> Self {
+        Self {
+            songs: StatefulList::with_items(Vec::new()),
+        }
+    }
+
+    /// Add the song to the end of the queue.
+    pub fn enqueue(&mut self, song: Song) {
+        self.songs.items.push(song);
+    }
+
+    /// Add the song to the front of the queue, it is played right after the current one.
+    pub fn play_next(&mut self, song: Song) {
+        self.songs.items.insert(0, song);
+        if let Some(i) = self.songs.state.selected() {
+            self.songs.state.select(Some(i + 1));
+        }
+    }
+
+    /// Add the songs to the front of the queue, before the ones queued since.
+    pub fn prepend(&mut self, songs: Vec<Song>) {
+        let len = songs.len();
+        self.songs.items.splice(0..0, songs);
+        if let Some(i) = self.songs.state.selected() {
+            self.songs.state.select(Some(i + len));
+        }
+    }
+
+    /// Take the first song out of the queue.
+    pub fn pop(&mut self) -> Option<Song> {
+        if self.songs.items.is_empty() {
+            return None;
+        }
+        let song = self.songs.items.remove(0);
+        if let Some(i) = self.songs.state.selected() {
+            self.songs.state.select(i.checked_sub(1).or(Some(0)));
+        }
+        self.fix_selection();
+        Some(song)
+    }
+
+    /// Take the selected song out of the queue.
+    pub fn remove(&mut self) -> Option<Song> {
+        let i = self.songs.state.selected()?;
+        if i >= self.songs.items.len() {
+            return None;
+        }
+        let song = self.songs.items.remove(i);
+        self.fix_selection();
+        Some(song)
+    }
+
+    /// Move the selected song one place up.
+    pub fn move_up(&mut self) {
+        if let Some(i) = self.songs.state.selected() {
+            if i > 0 && i < self.songs.items.len() {
+                self.songs.items.swap(i, i - 1);
+                self.songs.state.select(Some(i - 1));
+            }
+        }
+    }
+
+    /// Move the selected song one place down.
+    pub fn move_down(&mut self) {
+        if let Some(i) = self.songs.state.selected() {
+            if i + 1 < self.songs.items.len() {
+                self.songs.items.swap(i, i + 1);
+                self.songs.state.select(Some(i + 1));
+            }
+        }
+    }
+
+    /// Remove all songs.
+    pub fn clear(&mut self) {
+        self.songs.items.clear();
+        self.songs.state.select(None);
+    }
+
+    /// Keep the selection inside the queue.
+    fn fix_selection(&mut self) {
+        let len = self.songs.items.len();
+        match self.songs.state.selected() {
+            _ if len == 0 => self.songs.state.select(None),
+            Some(i) if i >= len => self.songs.state.select(Some(len - 1)),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta::Tags;
+    use std::path::PathBuf;
+
+    fn song(name: &str) -> Song {
+        Song {
+            name: name.to_string(),
+            rel_path: PathBuf::from(name),
+            time: 0.0,
+            tags: Tags::default(),
+            unplayable: false,
+            added: 0,
+        }
+    }
+
+    fn names(queue: &Queue) -> Vec<&str> {
+        queue.songs.items.iter().map(|s| s.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_queue() {
+        let mut queue = Queue::new();
+        queue.enqueue(song("a"));
+        queue.enqueue(song("b"));
+        queue.play_next(song("c"));
+        assert_eq!(names(&queue), ["c", "a", "b"]);
+
+        queue.songs.state.select(Some(2));
+        queue.move_up();
+        assert_eq!(names(&queue), ["c", "b", "a"]);
+        assert_eq!(queue.songs.state.selected(), Some(1));
+
+        assert_eq!(queue.pop().unwrap().name, "c");
+        assert_eq!(queue.songs.state.selected(), Some(0));
+        assert_eq!(queue.remove().unwrap().name, "b");
+        assert_eq!(names(&queue), ["a"]);
+
+        queue.clear();
+        assert!(queue.pop().is_none());
+        assert_eq!(queue.songs.state.selected(), None);
+    }
+}
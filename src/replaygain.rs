@@ -0,0 +1,94 @@
+use serde_derive::Deserialize;
+
+use crate::meta::Tags;
+
+/// Which ReplayGain adjustment is applied.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Mode {
+    #[default]
+    Off,
+    /// Every song is played at the same loudness.
+    Track,
+    /// Every album is played at the same loudness, keeping the differences inside an album.
+    Album,
+}
+
+/// The `[replaygain]` table.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ReplayGainConfig {
+    pub mode: Mode,
+    /// Extra gain in dB added to the tagged one.
+    pub preamp: f32,
+    /// Lower the gain so the peak of the song never goes beyond full scale.
+    pub prevent_clipping: bool,
+}
+
+impl Default for ReplayGainConfig {
+    fn default() -> Self {
+        Self {
+            mode: Mode::Off,
+            preamp: 0.0,
+            prevent_clipping: true,
+        }
+    }
+}
+
+impl ReplayGainConfig {
+    /// The amplification of the song, `1.0` if it is off or the song has no ReplayGain tags.
+    /// The other adjustment is used if the one of the mode is missing.
+    pub fn factor(&self, tags: &Tags) -> f32 {
+        let track = tags.track_gain.map(|gain| (gain, tags.track_peak));
+        let album = tags.album_gain.map(|gain| (gain, tags.album_peak));
+        let Some((gain, peak)) = (match self.mode {
+            Mode::Off => None,
+            Mode::Track => track.or(album),
+            Mode::Album => album.or(track),
+        }) else {
+            return 1.0;
+        };
+
+        let factor = 10f32.powf((gain + self.preamp) / 20.0);
+        match peak {
+            Some(peak) if self.prevent_clipping && peak > 0.0 => factor.min(1.0 / peak),
+            _ => factor,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_factor() {
+        let tags = Tags {
+            track_gain: Some(-6.0),
+            track_peak: Some(0.5),
+            album_gain: Some(6.0),
+            album_peak: Some(0.8),
+            ..Tags::default()
+        };
+        let mut config = ReplayGainConfig::default();
+        assert_eq!(config.factor(&tags), 1.0);
+
+        config.mode = Mode::Track;
+        assert!((config.factor(&tags) - 0.501).abs() < 0.001);
+
+        // +6dB would push the peak of 0.8 beyond full scale.
+        config.mode = Mode::Album;
+        assert_eq!(config.factor(&tags), 1.25);
+        config.prevent_clipping = false;
+        assert!((config.factor(&tags) - 1.995).abs() < 0.001);
+
+        // Track gain is used if album gain is missing.
+        let tags = Tags {
+            track_gain: Some(0.0),
+            ..Tags::default()
+        };
+        config.preamp = 6.0;
+        assert!((config.factor(&tags) - 1.995).abs() < 0.001);
+        assert_eq!(config.factor(&Tags::default()), 1.0);
+    }
+}
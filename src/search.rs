@@ -0,0 +1,136 @@
+use crate::app::Song;
+
+/// `Search` is the query typed after `/`, with the songs of library it matches.
+pub struct Search {
+    pub query: String,
+    /// Indices of the matching songs in library, in library order.
+    pub matches: Vec<usize>,
+    /// The match selected while the query is typed.
+    pub selected: usize,
+}
+
+impl Search {
+    pub fn new(query: &str, songs: &[Song]) -> Self {
+        Self {
+            query: query.to_string(),
+            matches: songs
+                .iter()
+                .enumerate()
+                .filter(|(_, song)| matches(query, song))
+                .map(|(i, _)| i)
+                .collect(),
+            selected: 0,
+        }
+    }
+
+    /// Whether the song at `idx` of library matches.
+    pub fn is_match(&self, idx: usize) -> bool {
+        self.matches.binary_search(&idx).is_ok()
+    }
+
+    /// The match selected while the query is typed.
+    pub fn selected(&self) -> Option<usize> {
+        self.matches.get(self.selected).copied()
+    }
+
+    /// Move the selection while the query is typed, it wraps around.
+    pub fn select_by(&mut self, forward: bool) {
+        let len = self.matches.len().max(1);
+        self.selected = match forward {
+            true => (self.selected + 1) % len,
+            false => (self.selected + len - 1) % len,
+        };
+    }
+
+    /// The first match after `from`, or before it if not `forward`, it wraps around.
+    pub fn next(&self, from: Option<usize>, forward: bool) -> Option<usize> {
+        let (first, last) = (self.matches.first()?, self.matches.last()?);
+        let Some(from) = from else {
+            return Some(*first);
+        };
+        match forward {
+            true => self.matches.iter().find(|&&i| i > from).or(Some(first)),
+            false => self
+                .matches
+                .iter()
+                .rev()
+                .find(|&&i| i < from)
+                .or(Some(last)),
+        }
+        .copied()
+    }
+}
+
+/// Whether the chars of `pattern` appear in `text` in the same order, ignoring case.
+fn fuzzy_match(pattern: &str, text: &str) -> bool {
+    let mut text = text.chars().flat_map(char::to_lowercase);
+    pattern
+        .chars()
+        .flat_map(char::to_lowercase)
+        .all(|p| text.any(|t| t == p))
+}
+
+/// Whether every word of the query fuzzily matches the title, artist, album or path of the song,
+/// so `beat abb` finds Abbey Road by the Beatles.
+pub fn matches(query: &str, song: &Song) -> bool {
+    let title = song.title();
+    let path = song.rel_path.to_string_lossy();
+    let fields = [
+        Some(title.as_str()),
+        song.tags.artist.as_deref(),
+        song.tags.album.as_deref(),
+        Some(path.as_ref()),
+    ];
+    query.split_whitespace().all(|word| {
+        fields
+            .iter()
+            .flatten()
+            .any(|field| fuzzy_match(word, field))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta::Tags;
+    use std::path::PathBuf;
+
+    fn song(path: &str, artist: &str, album: &str) -> Song {
+        Song {
+            name: format!("/music/{}", path),
+            rel_path: PathBuf::from(path),
+            time: 0.0,
+            tags: Tags {
+                artist: Some(artist.to_string()),
+                album: Some(album.to_string()),
+                ..Tags::default()
+            },
+            unplayable: false,
+            added: 0,
+        }
+    }
+
+    #[test]
+    fn test_search() {
+        let songs = [
+            song("01 Come Together.mp3", "The Beatles", "Abbey Road"),
+            song("01 Paranoid Android.mp3", "Radiohead", "OK Computer"),
+            song("02 Something.mp3", "The Beatles", "Abbey Road"),
+        ];
+        assert!(fuzzy_match("cmtgthr", "Come Together"));
+        assert!(!fuzzy_match("together come", "Come Together"));
+
+        let search = Search::new("beat abb", &songs);
+        assert_eq!(search.matches, [0, 2]);
+        assert_eq!(Search::new("PARANOID", &songs).matches, [1]);
+        assert!(Search::new("radiohead abbey", &songs).matches.is_empty());
+        assert_eq!(Search::new("", &songs).matches, [0, 1, 2]);
+
+        assert_eq!(search.next(None, true), Some(0));
+        assert_eq!(search.next(Some(0), true), Some(2));
+        assert_eq!(search.next(Some(2), true), Some(0));
+        assert_eq!(search.next(Some(1), false), Some(0));
+        assert_eq!(search.next(Some(0), false), Some(2));
+        assert!(search.is_match(2) && !search.is_match(1));
+    }
+}
@@ -0,0 +1,228 @@
+use serde_derive::Deserialize;
+use std::cmp::Ordering;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::app::Song;
+
+/// What the library is ordered by.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SortKey {
+    #[default]
+    Path,
+    Title,
+    /// By artist, then album and track.
+    Artist,
+    /// By album, then track.
+    Album,
+    Duration,
+    /// When the song was first scanned.
+    Added,
+}
+
+impl SortKey {
+    /// Switch to the next key, path -> title -> artist -> album -> duration -> added -> path.
+    pub fn cycle(self) -> Self {
+        match self {
+            SortKey::Path => SortKey::Title,
+            SortKey::Title => SortKey::Artist,
+            SortKey::Artist => SortKey::Album,
+            SortKey::Album => SortKey::Duration,
+            SortKey::Duration => SortKey::Added,
+            SortKey::Added => SortKey::Path,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SortKey::Path => "path",
+            SortKey::Title => "title",
+            SortKey::Artist => "artist",
+            SortKey::Album => "album",
+            SortKey::Duration => "duration",
+            SortKey::Added => "date added",
+        }
+    }
+}
+
+/// The `[sort]` table, the order of the library at startup.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(default)]
+pub struct Sort {
+    pub key: SortKey,
+    /// Reverse the order of the key, songs of an album still go by track.
+    pub descending: bool,
+}
+
+impl Sort {
+    /// Compare two songs by the key, ties go by album, track and path so the order never depends
+    /// on the previous one.
+    pub fn compare(&self, a: &Song, b: &Song) -> Ordering {
+        let reverse = |order: Ordering| match self.descending {
+            true => order.reverse(),
+            false => order,
+        };
+        // Songs without the tag go last, also when descending.
+        let text = |a: &Option<String>, b: &Option<String>, descending: bool| match (a, b) {
+            (Some(a), Some(b)) if descending => natural_cmp(a, b).reverse(),
+            (Some(a), Some(b)) => natural_cmp(a, b),
+            (a, b) => a.is_none().cmp(&b.is_none()),
+        };
+        let order = match self.key {
+            SortKey::Path => Ordering::Equal,
+            SortKey::Title => reverse(natural_cmp(&a.title(), &b.title())),
+            SortKey::Artist => text(&a.tags.artist, &b.tags.artist, self.descending),
+            SortKey::Album => text(&a.tags.album, &b.tags.album, self.descending),
+            SortKey::Duration => reverse(a.time.total_cmp(&b.time)),
+            SortKey::Added => reverse(a.added.cmp(&b.added)),
+        };
+        let album = match self.key {
+            SortKey::Artist => text(&a.tags.album, &b.tags.album, false)
+                .then(a.tags.disc.cmp(&b.tags.disc))
+                .then(a.tags.track.cmp(&b.tags.track)),
+            SortKey::Album => a
+                .tags
+                .disc
+                .cmp(&b.tags.disc)
+                .then(a.tags.track.cmp(&b.tags.track)),
+            _ => Ordering::Equal,
+        };
+        let path = natural_cmp(&a.name, &b.name);
+        let path = match self.key {
+            SortKey::Path => reverse(path),
+            _ => path,
+        };
+        order.then(album).then(path)
+    }
+
+    /// Describe the order, e.g. `Sorted by artist, descending`.
+    pub fn label(&self) -> String {
+        let order = if self.descending {
+            "descending"
+        } else {
+            "ascending"
+        };
+        format!("Sorted by {}, {}", self.key.label(), order)
+    }
+}
+
+/// Compare strings as people do: case-insensitively, with runs of digits compared as numbers, so
+/// `Track 2` comes before `Track 10`.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut x, mut y) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        let order = match (x.peek(), y.peek()) {
+            (None, None) => break,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(c), Some(d)) if c.is_ascii_digit() && d.is_ascii_digit() => {
+                let (m, n) = (number(&mut x), number(&mut y));
+                let (m, n) = (m.trim_start_matches('0'), n.trim_start_matches('0'));
+                m.len().cmp(&n.len()).then(m.cmp(n))
+            }
+            (Some(c), Some(d)) => {
+                let order = c.to_lowercase().cmp(d.to_lowercase());
+                x.next();
+                y.next();
+                order
+            }
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+    }
+    // Only the case or the leading zeros differ.
+    a.cmp(b)
+}
+
+/// Take the run of digits at the start.
+fn number(chars: &mut Peekable<Chars>) -> String {
+    std::iter::from_fn(|| chars.next_if(char::is_ascii_digit)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::meta::Tags;
+    use std::path::PathBuf;
+
+    fn song(name: &str, artist: Option<&str>, album: &str, track: u32, added: u64) -> Song {
+        Song {
+            name: name.to_string(),
+            rel_path: PathBuf::from(name),
+            time: track as f64,
+            tags: Tags {
+                artist: artist.map(String::from),
+                album: Some(album.to_string()),
+                track: Some(track),
+                ..Tags::default()
+            },
+            unplayable: false,
+            added,
+        }
+    }
+
+    fn sorted(songs: &[Song], key: SortKey, descending: bool) -> Vec<String> {
+        let mut songs = songs.to_vec();
+        songs.sort_by(|a, b| Sort { key, descending }.compare(a, b));
+        songs.into_iter().map(|song| song.name).collect()
+    }
+
+    #[test]
+    fn test_natural_cmp() {
+        let mut names = [
+            "Track 10", "track 2", "Track 1", "Track 02", "Track", "Track 1a",
+        ];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(
+            names,
+            ["Track", "Track 1", "Track 1a", "Track 02", "track 2", "Track 10"]
+        );
+        assert_eq!(
+            natural_cmp("a99999999999999999999", "a100000000000000000000"),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn test_sort() {
+        let songs = [
+            song("/m/b/10.mp3", Some("Beta"), "Second", 10, 3),
+            song("/m/a/2.mp3", Some("alpha"), "First", 2, 2),
+            song("/m/none.mp3", None, "First", 1, 1),
+            song("/m/b/9.mp3", Some("Beta"), "Second", 9, 3),
+        ];
+        assert_eq!(
+            sorted(&songs, SortKey::Path, false),
+            ["/m/a/2.mp3", "/m/b/9.mp3", "/m/b/10.mp3", "/m/none.mp3"]
+        );
+        assert_eq!(
+            sorted(&songs, SortKey::Path, true),
+            ["/m/none.mp3", "/m/b/10.mp3", "/m/b/9.mp3", "/m/a/2.mp3"]
+        );
+        assert_eq!(
+            sorted(&songs, SortKey::Artist, false),
+            ["/m/a/2.mp3", "/m/b/9.mp3", "/m/b/10.mp3", "/m/none.mp3"]
+        );
+        // Descending keeps the tracks of an album in order, and the untagged song last.
+        assert_eq!(
+            sorted(&songs, SortKey::Artist, true),
+            ["/m/b/9.mp3", "/m/b/10.mp3", "/m/a/2.mp3", "/m/none.mp3"]
+        );
+        assert_eq!(
+            sorted(&songs, SortKey::Album, false),
+            ["/m/none.mp3", "/m/a/2.mp3", "/m/b/9.mp3", "/m/b/10.mp3"]
+        );
+        assert_eq!(
+            sorted(&songs, SortKey::Duration, true),
+            ["/m/b/10.mp3", "/m/b/9.mp3", "/m/a/2.mp3", "/m/none.mp3"]
+        );
+        // Ties go by path.
+        assert_eq!(
+            sorted(&songs, SortKey::Added, false),
+            ["/m/none.mp3", "/m/a/2.mp3", "/m/b/9.mp3", "/m/b/10.mp3"]
+        );
+        assert_eq!(SortKey::Added.cycle(), SortKey::Path);
+    }
+}
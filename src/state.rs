@@ -0,0 +1,63 @@
+use color_eyre::Result;
+use serde_derive::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::config;
+use crate::mode::Repeat;
+use crate::volume::Volume;
+
+/// `State` is what is remembered between sessions.
+#[derive(Serialize, Deserialize, Debug, Default)]
+#[serde(default)]
+pub struct State {
+    /// Volume level in percent, full volume if unset.
+    pub volume: Option<u8>,
+    pub muted: bool,
+    /// The song playing when the session ended, and how far into it in seconds.
+    pub song: Option<PathBuf>,
+    pub position: f64,
+    /// Paths of the queued songs.
+    pub queue: Vec<PathBuf>,
+    /// The playback modes, the ones of the config are used if unset.
+    pub shuffle: Option<bool>,
+    pub repeat: Option<Repeat>,
+    /// Index of the tab shown.
+    pub tab: Option<usize>,
+}
+
+impl State {
+    /// The state file, `$XDG_STATE_HOME/rmu/state.toml`.
+    fn path() -> Result<PathBuf> {
+        let mut path = config::xdg_dir("XDG_STATE_HOME", ".local/state")?;
+        path.push("state.toml");
+        Ok(path)
+    }
+
+    /// Load the state of the last session, a missing or broken file is the default state.
+    pub fn load() -> Self {
+        Self::path()
+            .ok()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|contents| toml::from_str(&contents).ok())
+            .unwrap_or_default()
+    }
+
+    /// Write the state to a temporary file first, so it is never left half written.
+    pub fn save(&self) -> Result<()> {
+        let path = Self::path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("toml.tmp");
+        fs::write(&tmp, toml::to_string(self)?)?;
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+
+    pub fn volume(&self) -> Volume {
+        self.volume
+            .map(|level| Volume::new(level, self.muted))
+            .unwrap_or_default()
+    }
+}
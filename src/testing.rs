@@ -0,0 +1,54 @@
+//! Helpers to run the player in tests, without a sound card.
+
+use std::path::{Path, PathBuf};
+
+use crate::app::App;
+use crate::config::{Config, Scan};
+use crate::library;
+use crate::output::Output;
+use crate::playlist::Playlists;
+use crate::state::State;
+
+/// An empty directory for the test, remove it once done.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("rmu-{}-{}", name, std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Write `secs` seconds of silence as a 16 bit mono WAV file.
+pub fn write_wav(path: &Path, secs: u32) {
+    let rate = 8000u32;
+    let len = rate * secs * 2;
+    let mut wav = Vec::new();
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    // PCM, mono.
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&rate.to_le_bytes());
+    wav.extend_from_slice(&(rate * 2).to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&len.to_le_bytes());
+    wav.resize(wav.len() + len as usize, 0);
+    std::fs::write(path, wav).unwrap();
+}
+
+/// A player on a fake sound card, with the songs of `dir` in library. It starts from the default
+/// state and keeps its playlists and library cache in `dir`, whatever the last session on the
+/// machine left.
+pub fn fake_app<'a>(dir: &Path) -> App<'a> {
+    let config = Config {
+        path: dir.to_string_lossy().to_string(),
+        cache: Some(dir.join("library.toml")),
+        ..Config::default()
+    };
+    let playlists = Playlists::load(dir.join("playlists"));
+    let mut app = App::new(Output::Fake, config, &State::default(), playlists).unwrap();
+    app.tasks.items = library::read_paths(&[dir.to_path_buf()], &Scan::default()).unwrap();
+    app
+}
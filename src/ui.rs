@@ -1,19 +1,44 @@
-use crate::{app::*, term::Term};
+use crate::{
+    app::*,
+    browser::Column,
+    cli::Cli,
+    error::Error,
+    files::Entry,
+    ipc::Server,
+    library,
+    mpris::Mpris,
+    output::Output,
+    playlist::{self, Playlists},
+    state::State,
+    term::Term,
+};
 use color_eyre::Result;
 use crossterm::event::{self};
 use ratatui::{
-    layout::{Constraint, Layout, Rect},
+    layout::{Alignment, Constraint, Layout, Position, Rect},
     style::{Color, Modifier, Style},
     symbols,
     text::{Line, Span},
     widgets::*,
     Frame,
 };
-use rodio::OutputStream;
-use rodio::Sink;
+use rodio::{OutputStream, OutputStreamHandle};
 use std::time::Duration;
 use unicode_width::UnicodeWidthStr;
 
+/// Index of the library tab.
+pub const LIBRARY_TAB: usize = 0;
+/// Index of the visualizer tab.
+pub const VISUALIZER_TAB: usize = 1;
+/// Index of the queue tab.
+pub const QUEUE_TAB: usize = 2;
+/// Index of the playlists tab.
+pub const PLAYLIST_TAB: usize = 3;
+/// Index of the artist, album and track browser tab.
+pub const BROWSER_TAB: usize = 4;
+/// Index of the filesystem browser tab.
+pub const FILES_TAB: usize = 5;
+
 pub struct Tabstatus<'a> {
     pub titles: Vec<&'a str>,
     pub index: usize,
@@ -51,6 +76,9 @@ impl<T> StatefulList<T> {
     }
 
     pub fn next(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
         let i = match self.state.selected() {
             Some(i) => (i + 1) % self.items.len(),
             None => 0,
@@ -58,7 +86,22 @@ impl<T> StatefulList<T> {
         self.state.select(Some(i));
     }
 
+    pub fn first(&mut self) {
+        if !self.items.is_empty() {
+            self.state.select(Some(0));
+        }
+    }
+
+    pub fn last(&mut self) {
+        if !self.items.is_empty() {
+            self.state.select(Some(self.items.len() - 1));
+        }
+    }
+
     pub fn previous(&mut self) {
+        if self.items.is_empty() {
+            return;
+        }
         let i = match self.state.selected() {
             Some(i) => {
                 if i > 0 {
@@ -74,10 +117,15 @@ impl<T> StatefulList<T> {
 }
 
 /// Draw the song progress bar.
-fn draw_gauge(f: &mut Frame, app: &App, chunk: Rect) {
+fn draw_gauge(f: &mut Frame, app: &mut App, chunk: Rect) {
+    let title = match &app.current {
+        Some(song) => format!("{}  ", song.display()),
+        None => String::new(),
+    };
     let label = Span::styled(
         format!(
-            "{:02}:{:02}/{:02}:{:02}",
+            "{}{:02}:{:02}/{:02}:{:02}",
+            title,
             (app.cur_time / 60.0) as u64,
             (app.cur_time % 60.0) as u64,
             (app.tot_time / 60.0) as u64,
@@ -85,22 +133,55 @@ fn draw_gauge(f: &mut Frame, app: &App, chunk: Rect) {
         ),
         Style::default().fg(Color::Yellow),
     );
+    let label_width = label.width() as u16;
     let gauge = LineGauge::default()
         .filled_style(Style::default().fg(Color::Magenta))
         .line_set(symbols::line::THICK)
         .label(label)
         .ratio(app.progress);
-    f.render_widget(gauge, chunk);
+
+    // Show the playback modes and the volume on the right of the gauge.
+    let volume = app.volume.label();
+    let modes: Vec<&str> = [
+        app.shuffle.is_some().then_some("shuffle"),
+        Some(app.repeat.label()),
+        Some(volume.as_str()),
+    ]
+    .into_iter()
+    .flatten()
+    .filter(|mode| !mode.is_empty())
+    .collect();
+    let modes = modes.join(" ");
+    let chunks = Layout::horizontal([
+        Constraint::Min(0),
+        Constraint::Length(modes.len() as u16 + 1),
+    ])
+    .split(chunk);
+
+    // The line starts one column after the label, remember it for mouse seeking.
+    let line_x = (chunks[0].x + label_width + 1).min(chunks[0].right());
+    app.gauge_line = Rect::new(line_x, chunks[0].y, chunks[0].right() - line_x, 1);
+    f.render_widget(gauge, chunks[0]);
+    f.render_widget(
+        Paragraph::new(modes)
+            .style(Style::default().fg(Color::Cyan))
+            .alignment(Alignment::Right),
+        chunks[1],
+    );
 }
 
 /// Draw all songs's name and time in a list.
-fn draw_list(f: &mut Frame, app: &mut App, chunk: Rect) {
-    let tasks: Vec<ListItem> = app
-        .tasks
-        .items
-        .iter()
-        .map(|item| {
-            let name = item.name.split_at(item.name.rfind('/').unwrap() + 1).1;
+fn draw_list<'s>(
+    f: &mut Frame,
+    songs: impl Iterator<Item = &'s Song>,
+    highlight: impl Fn(usize) -> bool,
+    state: &mut ListState,
+    chunk: Rect,
+) {
+    let tasks: Vec<ListItem> = songs
+        .enumerate()
+        .map(|(i, item)| {
+            let name = item.display();
             let time = format!(
                 "{:02}:{:02}",
                 (item.time / 60.0) as u64,
@@ -108,14 +189,20 @@ fn draw_list(f: &mut Frame, app: &mut App, chunk: Rect) {
             );
 
             let padding = (chunk.width as usize)
-                .saturating_sub(UnicodeWidthStr::width(name))
+                .saturating_sub(UnicodeWidthStr::width(name.as_str()))
                 .saturating_sub(time.len());
-            ListItem::new(vec![Line::from(Span::raw(format!(
-                "{}{}{}",
-                name,
-                " ".repeat(padding),
-                time
-            )))])
+            let line = format!("{}{}{}", name, " ".repeat(padding), time);
+            // Songs which can not be decoded are crossed out.
+            let style = if item.unplayable {
+                Style::default()
+                    .fg(Color::DarkGray)
+                    .add_modifier(Modifier::CROSSED_OUT)
+            } else if highlight(i) {
+                Style::default().fg(Color::Yellow)
+            } else {
+                Style::default()
+            };
+            ListItem::new(vec![Line::from(Span::styled(line, style))])
         })
         .collect();
 
@@ -126,7 +213,33 @@ fn draw_list(f: &mut Frame, app: &mut App, chunk: Rect) {
                 .fg(Color::Blue)
                 .add_modifier(Modifier::BOLD),
         );
-    f.render_stateful_widget(tasks, chunk, &mut app.tasks.state);
+    f.render_stateful_widget(tasks, chunk, state);
+}
+
+/// Draw the bottom line, the prompt first, then the messages and the progress of the library
+/// scan.
+fn draw_status(f: &mut Frame, app: &App, chunk: Rect) {
+    if let Some(prompt) = &app.prompt {
+        let title = prompt.kind.title();
+        let line = Line::from(vec![
+            Span::styled(title, Style::default().fg(Color::Blue)),
+            Span::raw(prompt.input.value()),
+        ]);
+        f.render_widget(Paragraph::new(line), chunk);
+        f.set_cursor_position(Position::new(
+            chunk.x + title.width() as u16 + prompt.input.cursor_width(),
+            chunk.y,
+        ));
+    } else if let Some((msg, _)) = &app.status {
+        let text = Span::styled(msg.as_str(), Style::default().fg(Color::Red));
+        f.render_widget(Paragraph::new(Line::from(text)), chunk);
+    } else if let Some(scanning) = &app.scanning {
+        let text = Span::styled(
+            format!("Scanning {}/{} files", scanning.done, scanning.total),
+            Style::default().fg(Color::Yellow),
+        );
+        f.render_widget(Paragraph::new(Line::from(text)), chunk);
+    }
 }
 
 /// Draw the first tab.
@@ -135,37 +248,285 @@ fn draw_first_tab(f: &mut Frame, app: &mut App, chunk: Rect) {
         .constraints([Constraint::Length(1), Constraint::Min(0)].as_ref())
         .split(chunk);
     draw_gauge(f, app, chunks[0]);
-    draw_list(f, app, chunks[1]);
+    let searching = app.is_searching();
+    match &app.search {
+        // Only the matches are shown while the query is typed.
+        Some(search) if searching => {
+            let selected = search.selected().map(|_| search.selected);
+            let mut state = ListState::default().with_selected(selected);
+            let songs = search
+                .matches
+                .iter()
+                .filter_map(|&i| app.tasks.items.get(i));
+            draw_list(f, songs, |_| false, &mut state, chunks[1]);
+        }
+        Some(search) => draw_list(
+            f,
+            app.tasks.items.iter(),
+            |i| search.is_match(i),
+            &mut app.tasks.state,
+            chunks[1],
+        ),
+        None => draw_list(
+            f,
+            app.tasks.items.iter(),
+            |_| false,
+            &mut app.tasks.state,
+            chunks[1],
+        ),
+    }
 }
 
 /// Draw the second tab.
 fn draw_second_tab(f: &mut Frame, app: &App, chunk: Rect) {
+    let data: Vec<(&str, u64)> = app
+        .visualizer
+        .bars
+        .iter()
+        .map(|&bar| ("", bar as u64))
+        .collect();
+    // Spread the bars over the whole width, every bar is followed by a gap of one column.
+    let bar_width = (chunk.width / data.len() as u16).saturating_sub(1).max(1);
     let barchart = BarChart::default()
-        .data(&app.barchart_data)
-        .bar_width(7)
+        .data(&data)
+        .max(100)
+        .bar_width(bar_width)
         .bar_style(Style::default().fg(Color::Yellow))
         .value_style(Style::default().fg(Color::Yellow).bg(Color::Yellow));
     f.render_widget(barchart, chunk);
 }
 
+/// Draw the queue tab.
+fn draw_queue_tab(f: &mut Frame, app: &mut App, chunk: Rect) {
+    let chunks = Layout::default()
+        .constraints([Constraint::Length(1), Constraint::Min(0)].as_ref())
+        .split(chunk);
+    draw_gauge(f, app, chunks[0]);
+    let queue = &mut app.queue.songs;
+    draw_list(
+        f,
+        queue.items.iter(),
+        |_| false,
+        &mut queue.state,
+        chunks[1],
+    );
+}
+
+/// Draw the playlists tab, the saved playlists on the left and the songs of the selected one on
+/// the right.
+fn draw_playlist_tab(f: &mut Frame, app: &mut App, chunk: Rect) {
+    let chunks = Layout::default()
+        .constraints([Constraint::Length(1), Constraint::Min(0)].as_ref())
+        .split(chunk);
+    draw_gauge(f, app, chunks[0]);
+    let panes = Layout::horizontal([Constraint::Percentage(30), Constraint::Min(0)])
+        .spacing(1)
+        .split(chunks[1]);
+
+    let playlists = &mut app.playlists;
+    let names: Vec<ListItem> = playlists
+        .lists
+        .items
+        .iter()
+        .map(|playlist| {
+            let count = format!("{}", playlist.tracks.len());
+            let padding = (panes[0].width as usize)
+                .saturating_sub(UnicodeWidthStr::width(playlist.name.as_str()))
+                .saturating_sub(count.len());
+            ListItem::new(format!("{}{}{}", playlist.name, " ".repeat(padding), count))
+        })
+        .collect();
+    // The selected playlist is dimmed while its songs have the focus.
+    let highlight = match playlists.focus_songs {
+        true => Style::default().fg(Color::DarkGray),
+        false => Style::default().fg(Color::Blue),
+    };
+    let names = List::new(names).highlight_style(highlight.add_modifier(Modifier::BOLD));
+    f.render_stateful_widget(names, panes[0], &mut playlists.lists.state);
+    draw_list(
+        f,
+        playlists.songs.items.iter(),
+        |_| false,
+        &mut playlists.songs.state,
+        panes[1],
+    );
+}
+
+/// Draw a column of names, the selection is dimmed unless the column has the focus.
+fn draw_names<'s>(
+    f: &mut Frame,
+    names: impl Iterator<Item = &'s str>,
+    focused: bool,
+    state: &mut ListState,
+    chunk: Rect,
+) {
+    let highlight = match focused {
+        true => Style::default().fg(Color::Blue),
+        false => Style::default().fg(Color::DarkGray),
+    };
+    let names = List::new(names.map(ListItem::new).collect::<Vec<_>>())
+        .highlight_style(highlight.add_modifier(Modifier::BOLD));
+    f.render_stateful_widget(names, chunk, state);
+}
+
+/// Draw the browser tab, the artists, the albums of the selected artist and the songs of the
+/// selected album side by side.
+fn draw_browser_tab(f: &mut Frame, app: &mut App, chunk: Rect) {
+    let chunks = Layout::default()
+        .constraints([Constraint::Length(1), Constraint::Min(0)].as_ref())
+        .split(chunk);
+    draw_gauge(f, app, chunks[0]);
+    let panes = Layout::horizontal([
+        Constraint::Percentage(25),
+        Constraint::Percentage(25),
+        Constraint::Min(0),
+    ])
+    .spacing(1)
+    .split(chunks[1]);
+
+    let browser = &mut app.browser;
+    draw_names(
+        f,
+        browser
+            .artists
+            .items
+            .iter()
+            .map(|artist| artist.name.as_str()),
+        browser.column == Column::Artists,
+        &mut browser.artists.state,
+        panes[0],
+    );
+    draw_names(
+        f,
+        browser.albums.items.iter().map(String::as_str),
+        browser.column == Column::Albums,
+        &mut browser.albums.state,
+        panes[1],
+    );
+    draw_list(
+        f,
+        browser.tracks.items.iter(),
+        |_| false,
+        &mut browser.tracks.state,
+        panes[2],
+    );
+}
+
+/// Draw the files tab, the directory shown then its subdirectories and audio files.
+fn draw_files_tab(f: &mut Frame, app: &mut App, chunk: Rect) {
+    let chunks = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Length(1),
+        Constraint::Min(0),
+    ])
+    .split(chunk);
+    draw_gauge(f, app, chunks[0]);
+    let files = &mut app.files;
+    let dir = Paragraph::new(files.dir.to_string_lossy().to_string())
+        .style(Style::default().fg(Color::DarkGray));
+    f.render_widget(dir, chunks[1]);
+
+    let width = chunks[2].width as usize;
+    let entries: Vec<ListItem> = files
+        .entries
+        .items
+        .iter()
+        .map(|entry| {
+            let name = entry.name();
+            let (time, style) = match entry {
+                Entry::Dir(_) => (String::new(), Style::default().fg(Color::Cyan)),
+                Entry::Loading(_) => (String::from("--:--"), Style::default().fg(Color::DarkGray)),
+                Entry::Song(song) if song.unplayable => (
+                    String::new(),
+                    Style::default()
+                        .fg(Color::DarkGray)
+                        .add_modifier(Modifier::CROSSED_OUT),
+                ),
+                Entry::Song(song) => (
+                    format!(
+                        "{:02}:{:02}",
+                        (song.time / 60.0) as u64,
+                        (song.time % 60.0) as u64
+                    ),
+                    Style::default(),
+                ),
+            };
+            let padding = width
+                .saturating_sub(UnicodeWidthStr::width(name.as_str()))
+                .saturating_sub(time.len());
+            ListItem::new(Span::styled(
+                format!("{}{}{}", name, " ".repeat(padding), time),
+                style,
+            ))
+        })
+        .collect();
+    let entries = List::new(entries).highlight_style(
+        Style::default()
+            .fg(Color::Blue)
+            .add_modifier(Modifier::BOLD),
+    );
+    f.render_stateful_widget(entries, chunks[2], &mut files.entries.state);
+}
+
 /// Main logic about ui.
 pub fn ui(f: &mut Frame, app: &mut App) {
+    let status = app.prompt.is_some() || app.status.is_some() || app.scanning.is_some();
+    let chunks =
+        Layout::vertical([Constraint::Min(0), Constraint::Length(status as u16)]).split(f.area());
     match app.tabs.index {
-        0 => draw_first_tab(f, app, f.area()),
-        1 => draw_second_tab(f, app, f.area()),
+        LIBRARY_TAB => draw_first_tab(f, app, chunks[0]),
+        VISUALIZER_TAB => draw_second_tab(f, app, chunks[0]),
+        QUEUE_TAB => draw_queue_tab(f, app, chunks[0]),
+        PLAYLIST_TAB => draw_playlist_tab(f, app, chunks[0]),
+        BROWSER_TAB => draw_browser_tab(f, app, chunks[0]),
+        FILES_TAB => draw_files_tab(f, app, chunks[0]),
         _ => {}
     }
+    draw_status(f, app, chunks[1]);
 }
 
 /// Run the program, draw the terminal and handle the key pressed.
-pub async fn run() -> Result<()> {
+pub async fn run(cli: &Cli) -> Result<()> {
+    // Nothing can be played without the audio output, fail before taking over the terminal.
+    let (_stream, stream_handle) = OutputStream::try_default().map_err(Error::Output)?;
     let mut term = Term::new()?;
     term.start()?;
+    let result = run_app(&mut term, cli, stream_handle).await;
+    Term::restore()?;
+    result
+}
+
+/// Draw the terminal and handle the key pressed until quit.
+async fn run_app(term: &mut Term, cli: &Cli, stream_handle: OutputStreamHandle) -> Result<()> {
     let tick_rate = Duration::from_millis(200);
     let recover_delay = Duration::from_secs(3);
-    let (_stream, stream_handle) = OutputStream::try_default().unwrap();
-    let sink = Sink::try_new(&stream_handle).unwrap();
-    let mut app = App::new(sink).await;
+    let config = cli.load_config(true).await?;
+    let songs = library::read_paths(&cli.files, &config.scan)?;
+    let state = State::load();
+    let playlists = Playlists::load(playlist::dir()?);
+    let mut app = App::new(Output::Device(stream_handle), config, &state, playlists)?;
+    app.scan();
+    app.resume(state, songs.is_empty());
+    if let Some(tab) = cli.tab {
+        app.tabs.index = tab.index();
+    }
+    app.play_songs(songs);
+    // The player still works without remote control.
+    let mut server = match cli.socket_path().and_then(Server::bind) {
+        Ok(server) => Some(server),
+        Err(e) => {
+            app.notify(format!("No remote control: {}", e));
+            None
+        }
+    };
+
+    let mut mpris = match Mpris::new().await {
+        Ok(mpris) => Some(mpris),
+        Err(e) => {
+            app.notify(format!("No media keys: {}", e));
+            None
+        }
+    };
 
     loop {
         term.terminal.draw(|f| ui(f, &mut app))?;
@@ -179,6 +540,15 @@ pub async fn run() -> Result<()> {
             }
         }
 
+        if let Some(server) = &mut server {
+            server.poll(&mut app);
+        }
+        if let Some(mpris) = &mut mpris {
+            if let Err(e) = mpris.update(&mut app).await {
+                app.notify(format!("Media keys: {}", e));
+            }
+        }
+
         // Just use timer to update special stuff.
         app.update(tick_rate);
         app.recover_select(recover_delay);
@@ -188,6 +558,7 @@ pub async fn run() -> Result<()> {
         }
     }
 
-    Term::restore()?;
+    // The state is only a convenience, failing to write it must not stop the exit.
+    let _ = app.save_state();
     Ok(())
 }
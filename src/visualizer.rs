@@ -0,0 +1,277 @@
+use rodio::{cpal::FromSample, source::SeekError, Sample, Source};
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+use serde_derive::Deserialize;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Number of samples used by every FFT.
+const FFT_SIZE: usize = 2048;
+/// Lowest and highest frequency shown.
+const MIN_FREQ: f32 = 20.0;
+const MAX_FREQ: f32 = 20000.0;
+/// Magnitudes below this level are shown as empty bars.
+const FLOOR_DB: f32 = -60.0;
+
+/// The `[visualizer]` table.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct VisualizerConfig {
+    /// Number of frequency bands.
+    pub bars: usize,
+    /// How much of the previous frame is kept when a bar rises, from `0.0` to `1.0`.
+    pub smoothing: f64,
+    /// How many percent a bar is allowed to drop on every tick.
+    pub falloff: f64,
+}
+
+impl Default for VisualizerConfig {
+    fn default() -> Self {
+        Self {
+            bars: 32,
+            smoothing: 0.5,
+            falloff: 8.0,
+        }
+    }
+}
+
+/// The latest samples played, mixed down to mono.
+#[derive(Default)]
+struct Samples {
+    data: VecDeque<f32>,
+    sample_rate: u32,
+    /// The sink shown, taps of retired sinks stop copying so a crossfade does not interleave two
+    /// streams.
+    sink: u64,
+}
+
+/// `Tap` passes the samples of a source through while copying them into the visualizer.
+pub struct Tap<S> {
+    inner: S,
+    samples: Arc<Mutex<Samples>>,
+    /// The sink the source was appended to.
+    sink: u64,
+    /// Mono mix of the current frame.
+    sum: f32,
+    channel: u16,
+}
+
+impl<S> Iterator for Tap<S>
+where
+    S: Source,
+    S::Item: Sample,
+    f32: FromSample<S::Item>,
+{
+    type Item = S::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = self.inner.next()?;
+        self.sum += f32::from_sample_(sample);
+        self.channel += 1;
+
+        let channels = self.inner.channels().max(1);
+        if self.channel >= channels {
+            if let Ok(mut samples) = self.samples.try_lock() {
+                if samples.sink == self.sink {
+                    if samples.data.len() >= FFT_SIZE {
+                        samples.data.pop_front();
+                    }
+                    samples.data.push_back(self.sum / channels as f32);
+                    samples.sample_rate = self.inner.sample_rate();
+                }
+            }
+            self.sum = 0.0;
+            self.channel = 0;
+        }
+        Some(sample)
+    }
+}
+
+impl<S> Source for Tap<S>
+where
+    S: Source,
+    S::Item: Sample,
+    f32: FromSample<S::Item>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.inner.current_frame_len()
+    }
+
+    fn channels(&self) -> u16 {
+        self.inner.channels()
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.inner.sample_rate()
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        self.inner.total_duration()
+    }
+
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.inner.try_seek(pos)
+    }
+}
+
+/// `Visualizer` turns the samples played into log-spaced frequency bands.
+pub struct Visualizer {
+    config: VisualizerConfig,
+    samples: Arc<Mutex<Samples>>,
+    fft: Arc<dyn Fft<f32>>,
+    /// Height of every bar, from `0.0` to `100.0`.
+    pub bars: Vec<f64>,
+}
+
+impl Visualizer {
+    pub fn new(config: VisualizerConfig) -> Self {
+        let bars = vec![0.0; config.bars.max(1)];
+        Self {
+            config,
+            samples: Arc::default(),
+            fft: FftPlanner::new().plan_fft_forward(FFT_SIZE),
+            bars,
+        }
+    }
+
+    /// Wrap the source so its samples are shown.
+    pub fn tap<S>(&self, source: S) -> Tap<S>
+    where
+        S: Source,
+        S::Item: Sample,
+        f32: FromSample<S::Item>,
+    {
+        Tap {
+            inner: source,
+            samples: self.samples.clone(),
+            sink: self.samples.lock().map_or(0, |samples| samples.sink),
+            sum: 0.0,
+            channel: 0,
+        }
+    }
+
+    /// Only show the sources tapped from now on, the sink playing the others is fading out.
+    pub fn retire(&self) {
+        if let Ok(mut samples) = self.samples.lock() {
+            samples.sink += 1;
+        }
+    }
+
+    /// Compute the next frame, all bars fall down when nothing is playing.
+    pub fn update(&mut self, playing: bool) {
+        let target = if playing {
+            self.spectrum()
+        } else {
+            vec![0.0; self.bars.len()]
+        };
+
+        for (bar, value) in self.bars.iter_mut().zip(target) {
+            *bar = if value >= *bar {
+                *bar * self.config.smoothing + value * (1.0 - self.config.smoothing)
+            } else {
+                value.max(*bar - self.config.falloff)
+            };
+        }
+    }
+
+    /// Magnitude of every band in percent.
+    fn spectrum(&self) -> Vec<f64> {
+        let n = self.bars.len();
+        let (mut buffer, sample_rate) = {
+            let Ok(samples) = self.samples.lock() else {
+                return vec![0.0; n];
+            };
+            if samples.data.len() < FFT_SIZE || samples.sample_rate == 0 {
+                return vec![0.0; n];
+            }
+            // Hann window to reduce the spectral leakage.
+            let buffer: Vec<Complex<f32>> = samples
+                .data
+                .iter()
+                .enumerate()
+                .map(|(i, &s)| {
+                    let w =
+                        0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / FFT_SIZE as f32).cos();
+                    Complex::new(s * w, 0.0)
+                })
+                .collect();
+            (buffer, samples.sample_rate as f32)
+        };
+        self.fft.process(&mut buffer);
+
+        let bin_width = sample_rate / FFT_SIZE as f32;
+        let max_freq = MAX_FREQ.min(sample_rate / 2.0);
+        let ratio = (max_freq / MIN_FREQ).powf(1.0 / n as f32);
+        (0..n)
+            .map(|band| {
+                let low = MIN_FREQ * ratio.powi(band as i32);
+                let high = low * ratio;
+                let first = ((low / bin_width) as usize).max(1);
+                let last = ((high / bin_width) as usize).clamp(first, FFT_SIZE / 2 - 1);
+                let peak = buffer[first..=last]
+                    .iter()
+                    .map(|c| c.norm())
+                    .fold(0.0, f32::max);
+                // Normalize so a full scale sine reaches 0dB.
+                let db = 20.0 * (peak * 4.0 / FFT_SIZE as f32).max(1e-9).log10();
+                (((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0) * 100.0) as f64
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rodio::source::SineWave;
+
+    #[test]
+    fn test_spectrum() {
+        let mut visualizer = Visualizer::new(VisualizerConfig {
+            bars: 16,
+            smoothing: 0.0,
+            falloff: 100.0,
+        });
+        let source = visualizer.tap(SineWave::new(1000.0).take_duration(Duration::from_secs(1)));
+        assert_eq!(source.count(), 48000);
+
+        visualizer.update(true);
+        let loudest = visualizer
+            .bars
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(i, _)| i)
+            .unwrap();
+        // 1kHz is in the band [20 * 1000^(9/16), 20 * 1000^(10/16)) = [974Hz, 1500Hz).
+        assert_eq!(loudest, 9);
+
+        visualizer.update(false);
+        assert!(visualizer.bars.iter().all(|&bar| bar == 0.0));
+    }
+
+    #[test]
+    fn test_retire() {
+        let mut visualizer = Visualizer::new(VisualizerConfig {
+            bars: 16,
+            smoothing: 0.0,
+            falloff: 100.0,
+        });
+        let old = visualizer.tap(SineWave::new(1000.0).take_duration(Duration::from_secs(1)));
+        visualizer.retire();
+        let new = visualizer.tap(SineWave::new(4000.0).take_duration(Duration::from_secs(1)));
+        // Played together, as during a crossfade.
+        assert_eq!(old.zip(new).count(), 48000);
+
+        visualizer.update(true);
+        let loudest = visualizer
+            .bars
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(b.1))
+            .map(|(i, _)| i)
+            .unwrap();
+        // 4kHz is in the band [20 * 1000^(12/16), 20 * 1000^(13/16)) = [3557Hz, 5468Hz).
+        assert_eq!(loudest, 12);
+    }
+}
@@ -0,0 +1,116 @@
+/// Volume levels go from 0 to this, in percent.
+const MAX_LEVEL: u8 = 100;
+/// The lowest level above zero is this many dB below the full level.
+const RANGE_DB: f32 = 50.0;
+
+/// `Volume` is a level in percent mapped to a gain by a logarithmic curve, so every step sounds
+/// like the same change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Volume {
+    level: u8,
+    muted: bool,
+}
+
+impl Volume {
+    pub fn new(level: u8, muted: bool) -> Self {
+        Self {
+            level: level.min(MAX_LEVEL),
+            muted,
+        }
+    }
+
+    pub fn level(self) -> u8 {
+        self.level
+    }
+
+    pub fn is_muted(self) -> bool {
+        self.muted
+    }
+
+    /// Raise or lower the level by `delta` percent, unmuting it.
+    pub fn change(&mut self, delta: i16) {
+        self.level = i16::from(self.level)
+            .saturating_add(delta)
+            .clamp(0, i16::from(MAX_LEVEL)) as u8;
+        self.muted = false;
+    }
+
+    /// Mute or unmute, the level is kept while muted.
+    pub fn toggle_mute(&mut self) {
+        self.muted = !self.muted;
+    }
+
+    /// The amplification applied to the sink.
+    pub fn gain(self) -> f32 {
+        if self.muted || self.level == 0 {
+            return 0.0;
+        }
+        let ratio = f32::from(self.level) / f32::from(MAX_LEVEL);
+        10f32.powf(RANGE_DB * (ratio - 1.0) / 20.0)
+    }
+
+    /// The level whose gain is closest to `gain`, the inverse of `gain`.
+    pub fn level_for_gain(gain: f64) -> u8 {
+        if gain.is_nan() || gain <= 0.0 {
+            return 0;
+        }
+        let ratio = 1.0 + 20.0 * gain.log10() / f64::from(RANGE_DB);
+        (ratio * f64::from(MAX_LEVEL))
+            .round()
+            .clamp(0.0, f64::from(MAX_LEVEL)) as u8
+    }
+
+    /// The text of the volume indicator.
+    pub fn label(self) -> String {
+        if self.muted {
+            String::from("muted")
+        } else {
+            format!("vol {}%", self.level)
+        }
+    }
+}
+
+impl Default for Volume {
+    fn default() -> Self {
+        Self::new(MAX_LEVEL, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_volume() {
+        let mut volume = Volume::default();
+        assert_eq!(volume.gain(), 1.0);
+        volume.change(20);
+        assert_eq!(volume.level(), 100);
+
+        // Half the level is 25dB down.
+        volume.change(-50);
+        assert!((volume.gain() - 0.0562).abs() < 0.001);
+
+        volume.toggle_mute();
+        assert_eq!((volume.gain(), volume.label().as_str()), (0.0, "muted"));
+        // Changing the level unmutes it, the level is kept.
+        volume.change(5);
+        assert_eq!(volume.label(), "vol 55%");
+
+        volume.change(-200);
+        assert_eq!((volume.level(), volume.gain()), (0, 0.0));
+        volume.change(i16::MAX);
+        assert_eq!(volume.level(), 100);
+        volume.change(i16::MIN);
+        assert_eq!(volume.level(), 0);
+        assert_eq!(Volume::new(250, false).level(), 100);
+
+        assert_eq!(
+            Volume::level_for_gain(Volume::new(37, false).gain().into()),
+            37
+        );
+        assert_eq!(Volume::level_for_gain(2.0), 100);
+        assert_eq!(Volume::level_for_gain(1e-9), 0);
+        assert_eq!(Volume::level_for_gain(f64::NAN), 0);
+    }
+}
@@ -0,0 +1,159 @@
+use color_eyre::{eyre::eyre, Result};
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+
+use crate::app::Song;
+
+/// How long nothing has to change before the changes are applied, so files being copied are
+/// complete.
+const QUIET: Duration = Duration::from_millis(500);
+
+/// `Watch` follows the files added, removed, renamed or changed under the music directory.
+pub struct Watch {
+    _watcher: RecommendedWatcher,
+    rx: mpsc::UnboundedReceiver<notify::Result<notify::Event>>,
+    /// Paths changed since they were last taken.
+    changed: BTreeSet<PathBuf>,
+    /// When the last path changed.
+    last: Instant,
+}
+
+impl Watch {
+    /// Watch the directory and all its subdirectories.
+    pub fn new(root: &Path) -> Result<Self> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            let _ = tx.send(event);
+        })?;
+        watcher
+            .watch(root, RecursiveMode::Recursive)
+            .map_err(|e| eyre!("Can not watch `{}`: {}", root.display(), e))?;
+        Ok(Self {
+            _watcher: watcher,
+            rx,
+            changed: BTreeSet::new(),
+            last: Instant::now(),
+        })
+    }
+
+    /// The paths changed, once nothing changed for a while. An error means changes were lost,
+    /// when the kernel queue overflowed for instance, only a rescan finds them.
+    pub fn poll(&mut self) -> Result<Option<Vec<PathBuf>>> {
+        while let Ok(event) = self.rx.try_recv() {
+            let event = match event {
+                Ok(event) => event,
+                Err(e) => {
+                    // The rescan reads these too.
+                    self.changed.clear();
+                    return Err(eyre!("Missed changes of the music directory: {}", e));
+                }
+            };
+            if !matches!(event.kind, EventKind::Access(_)) {
+                self.changed.extend(event.paths);
+                self.last = Instant::now();
+            }
+        }
+        if self.changed.is_empty() || self.last.elapsed() < QUIET {
+            return Ok(None);
+        }
+        Ok(Some(
+            std::mem::take(&mut self.changed).into_iter().collect(),
+        ))
+    }
+}
+
+/// Replace the songs under the changed paths with the ones read again from them, songs still
+/// there keep the date they were added. Returns how many songs were added and removed.
+pub fn apply(library: &mut Vec<Song>, changed: &[PathBuf], songs: Vec<Song>) -> (usize, usize) {
+    let mut dropped: HashMap<String, u64> = HashMap::new();
+    library.retain(|song| {
+        let keep = !changed
+            .iter()
+            .any(|path| Path::new(&song.name).starts_with(path));
+        if !keep {
+            dropped.insert(song.name.clone(), song.added);
+        }
+        keep
+    });
+
+    let mut added = 0;
+    let mut seen = HashSet::new();
+    for mut song in songs {
+        // A file is read twice if both it and its directory changed.
+        if !seen.insert(song.name.clone()) {
+            continue;
+        }
+        match dropped.remove(&song.name) {
+            Some(time) => song.added = time,
+            None => added += 1,
+        }
+        library.push(song);
+    }
+    (added, dropped.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::Scan, library, testing};
+    use std::fs;
+
+    fn names(songs: &[Song]) -> Vec<String> {
+        songs
+            .iter()
+            .map(|song| song.rel_path.to_string_lossy().to_string())
+            .collect()
+    }
+
+    /// Wait for the changes to settle.
+    fn changes(watch: &mut Watch) -> Vec<PathBuf> {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(5) {
+            if let Some(changed) = watch.poll().unwrap() {
+                return changed;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        panic!("no change seen");
+    }
+
+    #[test]
+    fn test_watch() {
+        let root = testing::temp_dir("watch");
+        fs::create_dir_all(root.join("Old")).unwrap();
+        testing::write_wav(&root.join("Old/1.wav"), 1);
+        testing::write_wav(&root.join("Old/2.wav"), 1);
+        let scan = Scan::default();
+        let mut songs = library::read_changed(&root, &[root.join("Old")], &scan, None);
+        songs.iter_mut().for_each(|song| song.added = 7);
+        songs.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(names(&songs), ["Old/1.wav", "Old/2.wav"]);
+
+        let mut watch = Watch::new(&root).unwrap();
+        fs::create_dir_all(root.join("New/.hidden")).unwrap();
+        testing::write_wav(&root.join("New/3.wav"), 1);
+        testing::write_wav(&root.join("New/.hidden/4.wav"), 1);
+        fs::remove_file(root.join("Old/2.wav")).unwrap();
+        testing::write_wav(&root.join("Old/1.wav"), 2);
+        let changed = changes(&mut watch);
+        assert!(changed.contains(&root.join("Old/2.wav")));
+
+        let read = library::read_changed(&root, &changed, &scan, None);
+        assert_eq!(apply(&mut songs, &changed, read), (1, 1));
+        songs.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(names(&songs), ["New/3.wav", "Old/1.wav"]);
+        assert_eq!((songs[1].time, songs[1].added), (2.0, 7));
+
+        fs::rename(root.join("New"), root.join("Renamed")).unwrap();
+        let changed = changes(&mut watch);
+        let read = library::read_changed(&root, &changed, &scan, None);
+        assert_eq!(apply(&mut songs, &changed, read), (1, 1));
+        songs.sort_by(|a, b| a.name.cmp(&b.name));
+        assert_eq!(names(&songs), ["Old/1.wav", "Renamed/3.wav"]);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}